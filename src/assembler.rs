@@ -0,0 +1,603 @@
+use crate::instruction::{Instruction, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// assembles the classic mnemonics that `disasm` prints back into a ROM:
+//
+//     ORG 0x200                ; where the ROM is loaded, 0x200 by default
+//     SPEED EQU 2              ; constants, also written SPEED = 2
+//     start:                   ; labels, optionally followed by an instruction
+//         LD I, ship
+//         DRW V0, V1, 4
+//         JP start
+//     ship:
+//         SPRITE "..####.." ".######."  ; sprite rows, # is a lit pixel
+//         DB 0x18, %00111100, 60   ; bytes, in hex, binary or decimal
+//         DW 0x1234, start + 2     ; big-endian words
+//     INCLUDE "more.asm"       ; relative to the including file
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct AssembleError {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub message: String,
+}
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}: {}", file.display(), self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+impl std::error::Error for AssembleError {}
+
+// where a line came from, for error messages
+#[derive(Debug, Clone)]
+struct Origin {
+    file: Option<PathBuf>,
+    line: usize,
+}
+impl Origin {
+    fn error(&self, message: impl Into<String>) -> AssembleError {
+        AssembleError {
+            file: self.file.clone(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+}
+
+enum Item {
+    Instruction { mnemonic: String, operands: Vec<String> },
+    Bytes(Vec<String>), // expressions, one byte each
+    Words(Vec<String>), // expressions, two bytes each
+    Raw(Vec<u8>),       // already known, from SPRITE
+}
+impl Item {
+    fn size(&self) -> usize {
+        match self {
+            // LD I, LONG addr is the only 4-byte instruction
+            Item::Instruction { mnemonic, operands } if mnemonic == "LD" && operands.get(1).and_then(|op| long_operand(op)).is_some() => 4,
+            Item::Instruction { .. } => 2,
+            Item::Bytes(values) => values.len(),
+            Item::Words(values) => values.len() * 2,
+            Item::Raw(bytes) => bytes.len(),
+        }
+    }
+}
+
+struct Placed {
+    addr: u32,
+    item: Item,
+    origin: Origin,
+}
+
+enum Symbol {
+    Address(u16),
+    Constant(String, Origin), // evaluated when used, so it can refer to later labels
+}
+
+struct Assembler {
+    start: Option<u32>, // the first ORG, or 0x200 once anything is emitted
+    here: u32,
+    items: Vec<Placed>,
+    symbols: HashMap<String, Symbol>,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut asm = Assembler::new();
+    asm.read(source, None, Path::new("."), 0)?;
+    asm.finish()
+}
+
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AssembleError> {
+    let source = fs::read_to_string(path).map_err(|e| AssembleError {
+        file: Some(path.to_path_buf()),
+        line: 0,
+        message: format!("could not read file: {}", e),
+    })?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut asm = Assembler::new();
+    asm.read(&source, Some(path.to_path_buf()), dir, 0)?;
+    asm.finish()
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            start: None,
+            here: 0x200,
+            items: Vec::new(),
+            symbols: HashMap::new(),
+        }
+    }
+
+    // first pass: lay out every line and collect the symbols
+    fn read(
+        &mut self,
+        source: &str,
+        file: Option<PathBuf>,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AssembleError> {
+        for (number, line) in source.lines().enumerate() {
+            let origin = Origin {
+                file: file.clone(),
+                line: number + 1,
+            };
+            let mut rest = strip_comment(line).trim();
+            if rest.is_empty() {
+                continue;
+            }
+
+            // a label, with or without an instruction after it
+            if let Some((name, after)) = split_label(rest) {
+                self.define(name, Symbol::Address(self.here as u16), &origin)?;
+                rest = after.trim();
+                if rest.is_empty() {
+                    continue;
+                }
+            }
+
+            let (first, operands) = split_word(rest);
+            let (second, value) = split_word(operands);
+            // NAME EQU value / NAME = value
+            if second.eq_ignore_ascii_case("equ") || second == "=" {
+                self.define(first, Symbol::Constant(value.to_string(), origin.clone()), &origin)?;
+                continue;
+            }
+            if let Some(value) = operands.strip_prefix('=').filter(|_| !first.contains('=')) {
+                self.define(first, Symbol::Constant(value.trim().to_string(), origin.clone()), &origin)?;
+                continue;
+            }
+
+            let mnemonic = first.to_ascii_uppercase();
+            match mnemonic.as_str() {
+                "ORG" => {
+                    let addr = self.eval(operands, &origin, 0)?;
+                    if self.start.is_none() && self.items.is_empty() {
+                        self.start = Some(addr);
+                    } else if addr < self.here {
+                        return Err(origin.error(format!(
+                            "ORG {:#05X} is behind the current address {:#05X}",
+                            addr, self.here
+                        )));
+                    }
+                    self.here = addr;
+                }
+                "INCLUDE" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(origin.error("includes are nested too deeply"));
+                    }
+                    let name = parse_string(operands).ok_or_else(|| origin.error("INCLUDE needs a quoted file name"))?;
+                    let path = dir.join(name);
+                    let included = fs::read_to_string(&path)
+                        .map_err(|e| origin.error(format!("could not include {:?}: {}", path, e)))?;
+                    let included_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+                    self.read(&included, Some(path), &included_dir, depth + 1)?;
+                }
+                "DB" => self.place(Item::Bytes(split_operands(operands)), origin)?,
+                "DW" => self.place(Item::Words(split_operands(operands)), origin)?,
+                "SPRITE" => {
+                    let bytes = parse_sprite(operands).map_err(|e| origin.error(e))?;
+                    self.place(Item::Raw(bytes), origin)?;
+                }
+                _ => {
+                    let operands = split_operands(operands);
+                    self.place(Item::Instruction { mnemonic, operands }, origin)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol, origin: &Origin) -> Result<(), AssembleError> {
+        if !is_identifier(name) || parse_register(name).is_some() {
+            return Err(origin.error(format!("{:?} can't be used as a name", name)));
+        }
+        if self.symbols.contains_key(name) {
+            return Err(origin.error(format!("{:?} is already defined", name)));
+        }
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    fn place(&mut self, item: Item, origin: Origin) -> Result<(), AssembleError> {
+        self.start.get_or_insert(self.here);
+        let size = item.size() as u32;
+        if self.here + size > 0x10000 {
+            return Err(origin.error("the program runs past the end of memory"));
+        }
+        self.items.push(Placed {
+            addr: self.here,
+            item,
+            origin,
+        });
+        self.here += size;
+        Ok(())
+    }
+
+    // second pass: now every label is known, encode everything
+    fn finish(&self) -> Result<Vec<u8>, AssembleError> {
+        let Some(start) = self.start else {
+            return Ok(Vec::new());
+        };
+        let mut rom = Vec::new();
+        for placed in &self.items {
+            let offset = (placed.addr - start) as usize;
+            if rom.len() < offset {
+                rom.resize(offset, 0);
+            }
+            let origin = &placed.origin;
+            match &placed.item {
+                Item::Instruction { mnemonic, operands } => {
+                    let inst = self.instruction(mnemonic, operands, origin)?;
+                    rom.extend_from_slice(&inst.encode().to_be_bytes());
+                    if inst == Instruction::LongPointer {
+                        let addr = long_operand(&operands[1]).unwrap_or_default();
+                        let addr = self.eval(addr, origin, 0)?;
+                        if addr > 0xFFFF {
+                            return Err(origin.error(format!("{:#X} is past the 16-bit address space", addr)));
+                        }
+                        rom.extend_from_slice(&(addr as u16).to_be_bytes());
+                    }
+                }
+                Item::Bytes(values) => {
+                    for value in values {
+                        rom.push(self.byte(value, origin)?);
+                    }
+                }
+                Item::Words(values) => {
+                    for value in values {
+                        let word = self.eval(value, origin, 0)?;
+                        if word > 0xFFFF {
+                            return Err(origin.error(format!("{} doesn't fit in a word", word)));
+                        }
+                        rom.extend_from_slice(&(word as u16).to_be_bytes());
+                    }
+                }
+                Item::Raw(bytes) => rom.extend_from_slice(bytes),
+            }
+        }
+        Ok(rom)
+    }
+
+    fn eval(&self, expr: &str, origin: &Origin, depth: usize) -> Result<u32, AssembleError> {
+        // terms joined by + and -, evaluated left to right
+        if depth > 32 {
+            return Err(origin.error("constants refer to each other in a loop"));
+        }
+        let expr = expr.trim();
+        if expr.is_empty() {
+            return Err(origin.error("missing value"));
+        }
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        let mut terms = Vec::new();
+        for c in expr.chars() {
+            if (c == '+' || c == '-') && !term.trim().is_empty() {
+                terms.push((sign, term.trim().to_string()));
+                term.clear();
+                sign = if c == '+' { 1 } else { -1 };
+            } else if c == '-' && term.trim().is_empty() {
+                sign = -sign;
+            } else if c == '+' && term.trim().is_empty() {
+                continue;
+            } else {
+                term.push(c);
+            }
+        }
+        if term.trim().is_empty() {
+            return Err(origin.error(format!("incomplete expression {:?}", expr)));
+        }
+        terms.push((sign, term.trim().to_string()));
+
+        for (sign, term) in terms {
+            let value = match parse_number(&term) {
+                Some(n) => n as i64,
+                None => match self.symbols.get(term.as_str()) {
+                    Some(Symbol::Address(addr)) => *addr as i64,
+                    Some(Symbol::Constant(value, defined)) => {
+                        self.eval(value, defined, depth + 1)? as i64
+                    }
+                    None if is_identifier(&term) => {
+                        return Err(origin.error(format!("{:?} is not defined", term)))
+                    }
+                    None => return Err(origin.error(format!("can't read {:?} as a value", term))),
+                },
+            };
+            total += sign * value;
+        }
+        // negative values wrap the way a byte register would
+        if total < 0 {
+            if total < -0x8000 {
+                return Err(origin.error(format!("{} is out of range", total)));
+            }
+            total += if total < -0x80 { 0x10000 } else { 0x100 };
+        }
+        Ok(total as u32)
+    }
+
+    fn byte(&self, expr: &str, origin: &Origin) -> Result<u8, AssembleError> {
+        let value = self.eval(expr, origin, 0)?;
+        u8::try_from(value).map_err(|_| origin.error(format!("{} doesn't fit in a byte", value)))
+    }
+
+    fn nibble(&self, expr: &str, origin: &Origin) -> Result<u8, AssembleError> {
+        let value = self.eval(expr, origin, 0)?;
+        if value > 0xF {
+            return Err(origin.error(format!("{} doesn't fit in a nibble", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn addr(&self, expr: &str, origin: &Origin) -> Result<u16, AssembleError> {
+        let value = self.eval(expr, origin, 0)?;
+        if value > 0xFFF {
+            return Err(origin.error(format!("{:#X} is past the 12-bit address space", value)));
+        }
+        Ok(value as u16)
+    }
+
+    fn instruction(&self, mnemonic: &str, ops: &[String], origin: &Origin) -> Result<Instruction, AssembleError> {
+        let usage = |form: &str| origin.error(format!("expected {}", form));
+        let reg = |i: usize| ops.get(i).and_then(|op| parse_register(op));
+        let keyword = |i: usize, word: &str| ops.get(i).is_some_and(|op| op.eq_ignore_ascii_case(word));
+        let count = ops.len();
+        use Instruction::*;
+        let inst = match mnemonic {
+            "CLS" if count == 0 => ClearScreen,
+            "RET" if count == 0 => Return,
+            "SCR" if count == 0 => ScrollRight,
+            "SCL" if count == 0 => ScrollLeft,
+            "LOW" if count == 0 => HighResolution(false),
+            "HIGH" if count == 0 => HighResolution(true),
+            "SCD" if count == 1 => ScrollDown(self.nibble(&ops[0], origin)?),
+            "SCU" if count == 1 => ScrollUp(self.nibble(&ops[0], origin)?),
+            // the whole nibble, as FN01 decodes; only the low two bits pick planes
+            "PLANE" if count == 1 => SelectPlanes(self.nibble(&ops[0], origin)?),
+            "AUDIO" if count == 0 => LoadAudio,
+            "PITCH" if count == 1 => SetPitch(reg(0).ok_or_else(|| usage("Vx"))?),
+            "SAVE" | "LOAD" if count == 2 => {
+                let (Some(x), Some(y)) = (reg(0), reg(1)) else {
+                    return Err(usage("Vx, Vy"));
+                };
+                if mnemonic == "SAVE" {
+                    SaveRange { x, y }
+                } else {
+                    LoadRange { x, y }
+                }
+            }
+            "JP" if count == 1 => Jump(self.addr(&ops[0], origin)?),
+            "JP" if count == 2 && reg(0) == Some(0) => {
+                let addr = self.addr(&ops[1], origin)?;
+                JumpPlus {
+                    addr,
+                    x: (addr >> 8) as u8,
+                }
+            }
+            "CALL" if count == 1 => Call(self.addr(&ops[0], origin)?),
+            "SE" | "SNE" if count == 2 => {
+                let reg = reg(0).ok_or_else(|| usage("Vx, byte or Vx, Vy"))?;
+                let comp = match parse_register(&ops[1]) {
+                    Some(y) => Value::from_reg(y),
+                    None => Value::from_byte(self.byte(&ops[1], origin)?),
+                };
+                if mnemonic == "SE" {
+                    SkipIfEqual { reg, comp }
+                } else {
+                    SkipIfUnequal { reg, comp }
+                }
+            }
+            "SKP" if count == 1 => SkipIfKey(reg(0).ok_or_else(|| usage("Vx"))?),
+            "SKNP" if count == 1 => SkipIfNotKey(reg(0).ok_or_else(|| usage("Vx"))?),
+            "LD" if count == 2 => self.load(ops, origin)?,
+            "ADD" if count == 2 && keyword(0, "I") => AddPointer(reg(1).ok_or_else(|| usage("I, Vx"))?),
+            "ADD" if count == 2 => {
+                let x = reg(0).ok_or_else(|| usage("Vx, byte or Vx, Vy"))?;
+                match reg(1) {
+                    Some(y) => Add { x, y },
+                    None => AddInPlace {
+                        reg: x,
+                        byte: self.byte(&ops[1], origin)?,
+                    },
+                }
+            }
+            "OR" | "AND" | "XOR" | "SUB" | "SUBN" if count == 2 => {
+                let (Some(x), Some(y)) = (reg(0), reg(1)) else {
+                    return Err(usage("Vx, Vy"));
+                };
+                match mnemonic {
+                    "OR" => Or { x, y },
+                    "AND" => And { x, y },
+                    "XOR" => Xor { x, y },
+                    "SUB" => Sub { x, y },
+                    _ => SubReverse { x, y },
+                }
+            }
+            "SHR" | "SHL" if count == 1 || count == 2 => {
+                let x = reg(0).ok_or_else(|| usage("Vx or Vx, Vy"))?;
+                // without a VY the shift reads from VX, whichever quirk is set
+                let y = if count == 2 { reg(1).ok_or_else(|| usage("Vx, Vy"))? } else { x };
+                if mnemonic == "SHR" {
+                    ShiftRight { x, y }
+                } else {
+                    ShiftLeft { x, y }
+                }
+            }
+            "RND" if count == 2 => Random {
+                x: reg(0).ok_or_else(|| usage("Vx, byte"))?,
+                byte: self.byte(&ops[1], origin)?,
+            },
+            "DRW" if count == 3 => {
+                let (Some(x), Some(y)) = (reg(0), reg(1)) else {
+                    return Err(usage("Vx, Vy, nibble"));
+                };
+                match self.nibble(&ops[2], origin)? {
+                    0 => DrawLarge { x, y },
+                    byte_count => Draw { x, y, byte_count },
+                }
+            }
+            "CLS" | "RET" | "SCR" | "SCL" | "LOW" | "HIGH" | "SCD" | "JP" | "CALL" | "SE" | "SNE"
+            | "SKP" | "SKNP" | "LD" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR"
+            | "SHL" | "RND" | "DRW" | "SCU" | "PLANE" | "AUDIO" | "PITCH" | "SAVE" | "LOAD" => {
+                return Err(origin.error(format!("wrong number of operands for {}", mnemonic)))
+            }
+            _ => return Err(origin.error(format!("unknown instruction {:?}", mnemonic))),
+        };
+        Ok(inst)
+    }
+
+    // LD has the most forms by far
+    fn load(&self, ops: &[String], origin: &Origin) -> Result<Instruction, AssembleError> {
+        use Instruction::*;
+        let (dst, src) = (ops[0].to_ascii_uppercase(), ops[1].to_ascii_uppercase());
+        if let Some(x) = parse_register(&dst) {
+            return Ok(match src.as_str() {
+                "DT" => GetTimer(x),
+                "K" => KeyBlock(x),
+                "[I]" => LoadRegisters(x),
+                "R" => LoadRegistersRPL(x),
+                _ => Load {
+                    reg: x,
+                    value: match parse_register(&src) {
+                        Some(y) => Value::from_reg(y),
+                        None => Value::from_byte(self.byte(&ops[1], origin)?),
+                    },
+                },
+            });
+        }
+        if dst == "I" && long_operand(&ops[1]).is_some() {
+            return Ok(LongPointer);
+        }
+        if dst == "I" {
+            return Ok(SetPointer(self.addr(&ops[1], origin)?));
+        }
+        let x = parse_register(&src)
+            .ok_or_else(|| origin.error(format!("can't load {:?} into {:?}", ops[1], ops[0])))?;
+        Ok(match dst.as_str() {
+            "DT" => SetTimer(x),
+            "ST" => SetSound(x),
+            "F" => GetDigit(x),
+            "HF" => GetLargeDigit(x),
+            "B" => StoreDecimal(x),
+            "[I]" => StoreRegisters(x),
+            "R" => StoreRegistersRPL(x),
+            _ => return Err(origin.error(format!("can't load into {:?}", ops[0]))),
+        })
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    let (first, rest) = split_word(line);
+    let name = first.strip_suffix(':')?;
+    is_identifier(name).then_some((name, rest))
+}
+
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim_start()),
+        None => (s, ""),
+    }
+}
+
+fn split_operands(s: &str) -> Vec<String> {
+    if s.trim().is_empty() {
+        return Vec::new();
+    }
+    s.split(',').map(|op| op.trim().to_string()).collect()
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_register(s: &str) -> Option<u8> {
+    let s = s.trim();
+    let x = s.strip_prefix('V').or_else(|| s.strip_prefix('v'))?;
+    if x.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(x, 16).ok()
+}
+
+fn parse_number(s: &str) -> Option<u32> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('$')) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b").or_else(|| lower.strip_prefix('%')) {
+        u32::from_str_radix(bin, 2).ok()
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+// the address in `LONG addr`, the XO-CHIP 16-bit I load
+fn long_operand(op: &str) -> Option<&str> {
+    let (word, rest) = split_word(op);
+    (word.eq_ignore_ascii_case("long") && !rest.is_empty()).then_some(rest)
+}
+
+fn parse_string(s: &str) -> Option<&str> {
+    s.trim().strip_prefix('"')?.strip_suffix('"')
+}
+
+// rows of 8 or 16 pixels in quotes, # or X for lit and . or space for unlit
+fn parse_sprite(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return Err(String::from("SPRITE needs at least one quoted row"));
+    }
+    while !rest.is_empty() {
+        let row = rest
+            .strip_prefix('"')
+            .and_then(|r| r.find('"').map(|end| (&r[..end], &r[end + 1..])));
+        let Some((row, after)) = row else {
+            return Err(format!("expected a quoted sprite row at {:?}", rest));
+        };
+        if row.len() != 8 && row.len() != 16 {
+            return Err(format!("sprite rows are 8 or 16 pixels wide, {:?} is {}", row, row.len()));
+        }
+        let mut bits = 0u16;
+        for c in row.chars() {
+            bits <<= 1;
+            match c {
+                '#' | 'X' | 'x' | '1' => bits |= 1,
+                '.' | ' ' | '0' => (),
+                _ => return Err(format!("{:?} is not a sprite pixel", c)),
+            }
+        }
+        if row.len() == 16 {
+            bytes.extend_from_slice(&bits.to_be_bytes());
+        } else {
+            bytes.push(bits as u8);
+        }
+        rest = after.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+    Ok(bytes)
+}
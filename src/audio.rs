@@ -0,0 +1,255 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_TICK: u32 = SAMPLE_RATE / 60;
+
+// pitch and loudness of the buzzer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub frequency: f32, // in Hz
+    pub volume: f32,    // 0.0 is silent, 1.0 is full scale
+}
+impl Default for Tone {
+    fn default() -> Tone {
+        Tone {
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+// anything that can sound the buzzer, the emulator ticks it with the timers
+pub trait Beeper {
+    // called once per 60Hz tick, on is whether the sound timer is running
+    fn tick(&mut self, on: bool);
+    // XO-CHIP replaces the square wave with a 128-bit pattern played at a pitch
+    fn pattern(&mut self, _pattern: [u8; 16], _pitch: u8) {}
+    // back to the square wave, for a state loaded from before any pattern was
+    fn square(&mut self) {}
+    // called when the emulator is done, for backends that write out a file
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// a square wave that keeps its phase between calls, or an XO-CHIP pattern once one is set
+pub struct SquareWave {
+    tone: Tone,
+    sample_rate: f32,
+    step: f32, // phase advance per sample
+    phase: f32,
+    pattern: Option<[u8; 16]>,
+}
+impl SquareWave {
+    pub fn new(tone: Tone, sample_rate: u32) -> SquareWave {
+        SquareWave {
+            tone,
+            sample_rate: sample_rate as f32,
+            step: tone.frequency / sample_rate as f32,
+            phase: 0.0,
+            pattern: None,
+        }
+    }
+    pub fn set_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        // the pattern plays at 4000 bits a second at pitch 64, an octave per 48 steps
+        let bit_rate = 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+        self.step = bit_rate / 128.0 / self.sample_rate;
+        self.pattern = Some(pattern);
+    }
+    pub fn clear_pattern(&mut self) {
+        self.step = self.tone.frequency / self.sample_rate;
+        self.pattern = None;
+    }
+    pub fn next_sample(&mut self, on: bool) -> f32 {
+        if !on {
+            // every beep starts at the top of the wave
+            self.phase = 0.0;
+            return 0.0;
+        }
+        let high = match &self.pattern {
+            Some(pattern) => {
+                let bit = (self.phase * 128.0) as usize;
+                pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+            }
+            None => self.phase < 0.5,
+        };
+        self.phase = (self.phase + self.step).fract();
+        if high {
+            self.tone.volume
+        } else {
+            -self.tone.volume
+        }
+    }
+}
+
+// no sound at all, the default
+pub struct NullBeeper;
+impl Beeper for NullBeeper {
+    fn tick(&mut self, _on: bool) {}
+}
+
+// writes the buzzer to a 16-bit mono WAV file, one tick of samples at a time
+pub struct WavBeeper {
+    wave: SquareWave,
+    writer: BufWriter<File>,
+    samples: u32,
+    error: Option<io::Error>, // the first failed write, reported by finish
+}
+impl WavBeeper {
+    pub fn create(path: &Path, tone: Tone) -> io::Result<WavBeeper> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        // the sizes are filled in by finish, once they are known
+        write_wav_header(&mut writer, 0)?;
+        Ok(WavBeeper {
+            wave: SquareWave::new(tone, SAMPLE_RATE),
+            writer,
+            samples: 0,
+            error: None,
+        })
+    }
+}
+impl Beeper for WavBeeper {
+    fn tick(&mut self, on: bool) {
+        if self.error.is_some() {
+            return;
+        }
+        for _ in 0..SAMPLES_PER_TICK {
+            let sample = (self.wave.next_sample(on) * i16::MAX as f32) as i16;
+            if let Err(e) = self.writer.write_all(&sample.to_le_bytes()) {
+                self.error = Some(e);
+                return;
+            }
+            self.samples += 1;
+        }
+    }
+    fn pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        self.wave.set_pattern(pattern, pitch);
+    }
+    fn square(&mut self) {
+        self.wave.clear_pattern();
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_wav_header(&mut self.writer, self.samples)?;
+        self.writer.flush()
+    }
+}
+
+fn write_wav_header<W: Write>(writer: &mut W, samples: u32) -> io::Result<()> {
+    let data_size = samples * 2;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?; // size of the fmt chunk
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&1u16.to_le_bytes())?; // mono
+    writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // bytes per second
+    writer.write_all(&2u16.to_le_bytes())?; // bytes per sample
+    writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())
+}
+
+// plays the buzzer on the default output device
+#[cfg(feature = "audio")]
+pub use live::LiveBeeper;
+
+#[cfg(feature = "audio")]
+mod live {
+    use super::{Beeper, SquareWave, Tone};
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    // a pattern and its pitch, or None for the square wave
+    type Sound = Option<([u8; 16], u8)>;
+
+    // what the emulator thread hands to the audio thread
+    struct Shared {
+        on: AtomicBool,
+        pattern: Mutex<Option<Sound>>, // taken by the audio thread once applied
+    }
+
+    pub struct LiveBeeper {
+        shared: Arc<Shared>,
+        _stream: Stream, // playback stops when this is dropped
+    }
+    impl LiveBeeper {
+        pub fn open(tone: Tone) -> Result<LiveBeeper, String> {
+            let device = cpal::default_host()
+                .default_output_device()
+                .ok_or_else(|| String::from("no audio output device"))?;
+            let supported = device.default_output_config().map_err(|e| e.to_string())?;
+            let config = supported.config();
+            let shared = Arc::new(Shared {
+                on: AtomicBool::new(false),
+                pattern: Mutex::new(None),
+            });
+            let stream = match supported.sample_format() {
+                SampleFormat::F32 => build::<f32>(&device, &config, tone, shared.clone()),
+                SampleFormat::I16 => build::<i16>(&device, &config, tone, shared.clone()),
+                SampleFormat::U16 => build::<u16>(&device, &config, tone, shared.clone()),
+                format => return Err(format!("unsupported sample format {}", format)),
+            }
+            .map_err(|e| e.to_string())?;
+            stream.play().map_err(|e| e.to_string())?;
+            Ok(LiveBeeper {
+                shared,
+                _stream: stream,
+            })
+        }
+    }
+    impl Beeper for LiveBeeper {
+        fn tick(&mut self, on: bool) {
+            self.shared.on.store(on, Ordering::Relaxed);
+        }
+        fn pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+            if let Ok(mut pending) = self.shared.pattern.lock() {
+                *pending = Some(Some((pattern, pitch)));
+            }
+        }
+        fn square(&mut self) {
+            if let Ok(mut pending) = self.shared.pattern.lock() {
+                *pending = Some(None);
+            }
+        }
+    }
+
+    fn build<T: SizedSample + FromSample<f32>>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        tone: Tone,
+        shared: Arc<Shared>,
+    ) -> Result<Stream, cpal::BuildStreamError> {
+        let channels = config.channels as usize;
+        let mut wave = SquareWave::new(tone, config.sample_rate.0);
+        device.build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                // never wait on the emulator thread in here
+                if let Ok(mut pending) = shared.pattern.try_lock() {
+                    match pending.take() {
+                        Some(Some((pattern, pitch))) => wave.set_pattern(pattern, pitch),
+                        Some(None) => wave.clear_pattern(),
+                        None => (),
+                    }
+                }
+                let on = shared.on.load(Ordering::Relaxed);
+                for frame in data.chunks_mut(channels) {
+                    let sample = T::from_sample(wave.next_sample(on));
+                    frame.fill(sample);
+                }
+            },
+            |e| eprintln!("audio error: {}", e),
+            None,
+        )
+    }
+}
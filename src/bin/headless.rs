@@ -0,0 +1,333 @@
+use schip_emu::audio::{Tone, WavBeeper};
+use schip_emu::flags::FlagStore;
+use schip_emu::movie::Movie;
+use schip_emu::quirks::{Platform, QuirkPreset, Quirks};
+use schip_emu::random;
+use schip_emu::scheduler::Scheduler;
+use schip_emu::{parse_address, Emulator, EmulatorConfig};
+use clap::Parser;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "schip-headless",
+    version,
+    about = "Runs a SUPER-CHIP ROM without a window and dumps the final machine state"
+)]
+#[command(group = clap::ArgGroup::new("length").args(["cycles", "frames"]))]
+struct Args {
+    /// ROM file to run
+    rom: PathBuf,
+
+    /// address the ROM is loaded at (and where execution starts)
+    #[arg(long, default_value = "0x200", value_parser = parse_address)]
+    load_address: u16,
+
+    /// font file loaded over the built-in font at 0x000
+    #[arg(long)]
+    font: Option<PathBuf>,
+
+    /// machine to emulate (schip, xochip)
+    #[arg(long, default_value_t = Platform::default())]
+    platform: Platform,
+
+    /// quirk preset (chip8, schip10, schip11, modern, xochip), defaults to the platform's
+    #[arg(long)]
+    quirks: Option<QuirkPreset>,
+
+    /// 8XY4-8XYE write VF before the result, so with X = F it keeps the result instead of the flag (no preset does this)
+    #[arg(long)]
+    result_last: bool,
+
+    /// how many RPL flags FX75/FX85 reach, defaults to the platform's (8, or 16 on XO-CHIP)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=16))]
+    rpl_flags: Option<u8>,
+
+    /// load the ROM's RPL flags from this file, and write them back at the end
+    #[arg(long)]
+    flags_file: Option<PathBuf>,
+
+    /// boot from this save state instead of a fresh machine
+    #[arg(long)]
+    state: Option<PathBuf>,
+
+    /// stop after executing this many instructions, even partway through a played movie
+    #[arg(long)]
+    cycles: Option<u64>,
+
+    /// stop after this many 60Hz frames, a played movie's length by default
+    #[arg(long)]
+    frames: Option<u64>,
+
+    /// instructions executed per 60Hz frame [default: 20]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    ipf: Option<u32>,
+
+    /// instructions executed per second instead, rounded to a whole number per frame
+    #[arg(long, conflicts_with = "ipf", value_parser = clap::value_parser!(u32).range(60..))]
+    ips: Option<u32>,
+
+    /// key timeline: lines of `<frame> <key> <down|up>`, key in hex
+    #[arg(long)]
+    keys: Option<PathBuf>,
+
+    /// where CXNN's random numbers come from: a seed N, seeded:N, fixed:A,B,C (hex bytes) or vip:N
+    #[arg(long, value_parser = parse_rng)]
+    rng: Option<String>,
+
+    /// record the keys of every frame and the random source to this movie file
+    #[arg(long, conflicts_with = "state")]
+    record: Option<PathBuf>,
+
+    /// replay a movie: its keys, random source, platform, quirks, RPL flags and instructions per frame
+    #[arg(
+        long,
+        conflicts_with_all = ["state", "keys", "rng", "platform", "quirks", "result_last", "rpl_flags", "flags_file", "ipf", "ips"]
+    )]
+    play: Option<PathBuf>,
+
+    /// write the final display here (.pbm or .png)
+    #[arg(long)]
+    display_out: Option<PathBuf>,
+
+    /// write the final registers and RAM here as JSON
+    #[arg(long)]
+    state_out: Option<PathBuf>,
+
+    /// write a save state of the final machine here, loadable with --state
+    #[arg(long)]
+    save_state: Option<PathBuf>,
+
+    /// record the buzzer to this WAV file
+    #[arg(long)]
+    wav_out: Option<PathBuf>,
+
+    /// pitch of the buzzer in Hz
+    #[arg(long, default_value_t = 440.0)]
+    frequency: f32,
+
+    /// loudness of the buzzer, 0 to 100
+    #[arg(long, default_value_t = 25, value_parser = clap::value_parser!(u8).range(0..=100))]
+    volume: u8,
+}
+
+struct KeyEvent {
+    frame: u64,
+    key: usize,
+    down: bool,
+}
+
+fn parse_key_script(source: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let bad_line = || format!("line {}: expected `<frame> <key> <down|up>`, got {:?}", number + 1, line);
+        if fields.len() != 3 {
+            return Err(bad_line());
+        }
+        let frame = fields[0].parse::<u64>().map_err(|_| bad_line())?;
+        let key = usize::from_str_radix(fields[1].trim_start_matches("0x"), 16)
+            .ok()
+            .filter(|k| *k < 0x10)
+            .ok_or_else(bad_line)?;
+        let down = match fields[2] {
+            "down" => true,
+            "up" => false,
+            _ => return Err(bad_line()),
+        };
+        events.push(KeyEvent { frame, key, down });
+    }
+    events.sort_by_key(|e| e.frame);
+    Ok(events)
+}
+
+// checked up front so a typo fails before anything runs
+fn parse_rng(s: &str) -> Result<String, String> {
+    random::parse_source(s)?;
+    Ok(s.to_string())
+}
+
+fn write_display(emulator: &Emulator, path: &Path) -> Result<(), String> {
+    let is_png = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if is_png {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        emulator
+            .write_display_png(BufWriter::new(file))
+            .map_err(|e| e.to_string())
+    } else {
+        fs::write(path, emulator.display_pbm()).map_err(|e| e.to_string())
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let events = match &args.keys {
+        Some(path) => {
+            let source = fs::read_to_string(path)
+                .map_err(|e| format!("could not read {:?}: {}", path, e))?;
+            parse_key_script(&source)?
+        }
+        None => Vec::new(),
+    };
+
+    let movie = match &args.play {
+        Some(path) => Some(Movie::load(path).map_err(|e| format!("could not read {:?}: {}", path, e))?),
+        None => None,
+    };
+    // a movie only says when to stop if neither --frames nor --cycles does
+    let frames = match args.cycles {
+        Some(_) => args.frames,
+        None => args.frames.or(movie.as_ref().map(|movie| movie.len() as u64)),
+    };
+    if frames.is_none() && args.cycles.is_none() {
+        return Err(String::from("one of --cycles or --frames is needed to know when to stop"));
+    }
+
+    let mut emulator = Emulator::with_config(EmulatorConfig {
+        rom: args.rom,
+        load_address: args.load_address,
+        font: args.font,
+        platform: movie.as_ref().map_or(args.platform, |movie| movie.platform),
+        quirks: match &movie {
+            Some(movie) => movie.quirks,
+            None => Quirks {
+                flag_last: !args.result_last,
+                ..args.quirks.unwrap_or(args.platform.default_preset()).into()
+            },
+        },
+        rpl_flags: movie.as_ref().map_or(args.rpl_flags, |movie| Some(movie.rpl_flags.len() as u8)),
+    })
+    .map_err(|e| e.to_string())?;
+    if let Some(rng) = args.rng.or(movie.as_ref().map(|movie| movie.rng.clone())) {
+        emulator.set_rng(random::parse_source(&rng)?);
+    }
+    if movie.as_ref().is_some_and(|movie| movie.rom_hash != emulator.rom_hash()) {
+        eprintln!("warning: the movie was recorded with a different ROM");
+    }
+    let mut flags = match &args.flags_file {
+        Some(path) => Some(FlagStore::open(path).map_err(|e| format!("could not read {:?}: {}", path, e))?),
+        None => None,
+    };
+    if let Some(movie) = &movie {
+        emulator.set_rpl_flags(&movie.rpl_flags);
+    } else if let Some(saved) = flags.as_ref().and_then(|store| store.get(emulator.rom_hash())) {
+        emulator.set_rpl_flags(saved);
+    }
+    if let Some(path) = &args.state {
+        let state = fs::read(path).map_err(|e| format!("could not read {:?}: {}", path, e))?;
+        emulator.load_state(&state).map_err(|e| e.to_string())?;
+    }
+    if let Some(path) = &args.wav_out {
+        let tone = Tone {
+            frequency: args.frequency,
+            volume: args.volume as f32 / 100.0,
+        };
+        let beeper = WavBeeper::create(path, tone).map_err(|e| format!("could not write {:?}: {}", path, e))?;
+        emulator.set_beeper(Box::new(beeper));
+    }
+
+    // no real time to keep up with here, the scheduler only settles the budget
+    let ipf = match (&movie, args.ips) {
+        (Some(movie), _) => movie.ipf,
+        (None, Some(ips)) => Scheduler::from_ips(ips).get_ipf(),
+        (None, None) => Scheduler::new(args.ipf.unwrap_or(20)).get_ipf(),
+    };
+    let mut recording = args.record.as_ref().map(|_| Movie::new(&emulator, ipf));
+    let mut keys = [false; 0x10];
+    let mut next_event = 0;
+    let mut frame = 0u64;
+    let mut cycles = 0u64;
+    let mut failure = None;
+    let done = |frame: u64, cycles: u64| match (frames, args.cycles) {
+        (Some(frames), _) => frame >= frames,
+        (None, Some(max)) => cycles >= max,
+        (None, None) => true,
+    };
+
+    'frames: while !done(frame, cycles) {
+        while next_event < events.len() && events[next_event].frame <= frame {
+            keys[events[next_event].key] = events[next_event].down;
+            next_event += 1;
+        }
+        if let Some(movie) = &movie {
+            // every key is let go once the movie runs out
+            keys = movie.frame(frame as usize).unwrap_or_default();
+        }
+        emulator.update_keys(keys);
+        if let Some(recording) = &mut recording {
+            recording.push(keys);
+        }
+
+        let more_keys = match &movie {
+            Some(movie) => (frame as usize) < movie.len(),
+            None => next_event < events.len(),
+        };
+        if emulator.waiting_for_key() && !more_keys {
+            // nothing left in the script can ever release the CPU
+            eprintln!("stopped at frame {}: waiting for a key with no more key events", frame);
+            break;
+        }
+
+        for _ in 0..ipf {
+            if emulator.waiting_for_key() || emulator.waiting_for_vblank() || done(frame, cycles) {
+                break;
+            }
+            if let Err(e) = emulator.execute() {
+                failure = Some(e);
+                break 'frames;
+            }
+            cycles += 1;
+        }
+        emulator.tick_timers();
+        frame += 1;
+    }
+    println!("ran {} instructions over {} frames", cycles, frame);
+
+    if let Some(path) = &args.display_out {
+        write_display(&emulator, path).map_err(|e| format!("could not write {:?}: {}", path, e))?;
+    }
+    if let Some(path) = &args.state_out {
+        fs::write(path, emulator.state_json())
+            .map_err(|e| format!("could not write {:?}: {}", path, e))?;
+    }
+    if let Some(store) = &mut flags {
+        store.set(emulator.rom_hash(), emulator.rpl_flags());
+        store
+            .save()
+            .map_err(|e| format!("could not write {:?}: {}", store.path(), e))?;
+    }
+    if let Some(path) = &args.save_state {
+        fs::write(path, emulator.save_state()).map_err(|e| format!("could not write {:?}: {}", path, e))?;
+    }
+    if let (Some(path), Some(recording)) = (&args.record, &recording) {
+        recording.save(path).map_err(|e| format!("could not write {:?}: {}", path, e))?;
+    }
+    if let Some(path) = &args.wav_out {
+        emulator
+            .finish_beeper()
+            .map_err(|e| format!("could not write {:?}: {}", path, e))?;
+    }
+    match failure {
+        // the dumps above still show the state the machine crashed in
+        Some(e) => Err(format!("{} (frame {}, after {} instructions)", e, frame, cycles)),
+        None => Ok(()),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,6 +1,10 @@
+use crate::error::EmulatorError;
 use crate::Emulator;
 use std::fs::File;
 use std::io::{Read};
+use std::path::Path;
+
+pub const STACK_SIZE: usize = 16; // SCHIP has 16 levels of subroutine nesting
 
 #[derive(Debug)]
 pub struct Register {
@@ -13,6 +17,14 @@ impl Register {
     }
 }
 
+// a write to a watched RAM address
+#[derive(Debug, Clone, Copy)]
+pub struct RamWrite {
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
 pub enum Resolution {
     High,
     Low,
@@ -37,83 +49,171 @@ impl From<Resolution> for bool {
 
 impl Emulator {
     // memory accessor functions
-    pub fn get_ram(&self, addr: u16) -> u8 {
-        self.ram[addr as usize]
+    pub fn get_ram(&self, addr: u16) -> Result<u8, EmulatorError> {
+        self.ram
+            .get(addr as usize)
+            .copied()
+            .ok_or(EmulatorError::MemoryOutOfBounds { addr: addr as usize })
     }
-    pub fn get_ram_slice(&self, addr_a: u16, addr_b: u16) -> &[u8] {
+    pub fn get_ram_slice(&self, addr_a: u16, addr_b: u16) -> Result<&[u8], EmulatorError> {
         let a = addr_a as usize;
         let b = addr_b as usize;
-        &self.ram[a..b]
-    }
-    pub fn set_ram(&mut self, addr: u16, val: u8) {
-        self.ram[addr as usize] = val;
+        self.ram.get(a..b).ok_or(EmulatorError::MemoryOutOfBounds {
+            addr: a.max(self.ram.len()),
+        })
+    }
+    // the len bytes from addr, all of them or an error
+    pub fn get_ram_bytes(&self, addr: usize, len: usize) -> Result<&[u8], EmulatorError> {
+        self.ram.get(addr..addr + len).ok_or(EmulatorError::MemoryOutOfBounds {
+            addr: addr.max(self.ram.len()),
+        })
+    }
+    pub fn set_ram(&mut self, addr: u16, val: u8) -> Result<(), EmulatorError> {
+        match self.ram.get_mut(addr as usize) {
+            Some(byte) => {
+                if !self.ram_watches.is_empty() && self.ram_watches.contains(&addr) {
+                    self.ram_watch_hits.push(RamWrite { addr, old: *byte, new: val });
+                }
+                *byte = val;
+                Ok(())
+            }
+            None => Err(EmulatorError::MemoryOutOfBounds { addr: addr as usize }),
+        }
     }
-    pub fn get_rpl(&self, addr: u8) -> u8 {
-        // addr HAS to be between 0 and 7, nothing more.
-        self.rpl[addr as usize]
+    pub fn get_rpl(&self, addr: u8) -> Result<u8, EmulatorError> {
+        // addr HAS to be below the flag count (8, or 16 on XO-CHIP), nothing more.
+        self.rpl_flags()
+            .get(addr as usize)
+            .copied()
+            .ok_or(EmulatorError::RplOutOfBounds { index: addr })
     }
-    pub fn set_rpl(&mut self, addr: u8, val: u8) {
+    pub fn set_rpl(&mut self, addr: u8, val: u8) -> Result<(), EmulatorError> {
         // addr is same as get_rpl
-        self.rpl[addr as usize] = val;
+        match self.rpl[..self.rpl_count as usize].get_mut(addr as usize) {
+            Some(flag) => {
+                *flag = val;
+                Ok(())
+            }
+            None => Err(EmulatorError::RplOutOfBounds { index: addr }),
+        }
     }
-    pub fn push_callstack(&mut self, val: u16) {
+    pub fn rpl_flags(&self) -> &[u8] {
+        &self.rpl[..self.rpl_count as usize]
+    }
+    pub fn set_rpl_flags(&mut self, flags: &[u8]) {
+        // a file from a run with a different flag count fills what fits
+        let count = flags.len().min(self.rpl_count as usize);
+        self.rpl[..count].copy_from_slice(&flags[..count]);
+    }
+    pub fn push_callstack(&mut self, val: u16) -> bool {
+        // returns false if the stack is already full
+        if self.call_stack.len() >= STACK_SIZE {
+            return false;
+        }
         self.call_stack.push(val);
+        true
+    }
+    pub fn pop_callstack(&mut self) -> Option<u16> {
+        self.call_stack.pop()
+    }
+    pub fn get_call_stack(&self) -> &[u16] {
+        &self.call_stack
+    }
+
+    // RAM write watches, used by the debugger
+    pub fn watch_ram(&mut self, addr: u16) {
+        self.ram_watches.insert(addr);
     }
-    pub fn pop_callstack(&mut self) -> u16 {
-        self.call_stack
-            .pop()
-            .expect("there was nothing on the callstack!")
+    pub fn unwatch_ram(&mut self, addr: u16) {
+        self.ram_watches.remove(&addr);
+    }
+    pub fn get_ram_watches(&self) -> impl Iterator<Item = u16> + '_ {
+        self.ram_watches.iter().copied()
+    }
+    pub fn take_ram_watch_hits(&mut self) -> Vec<RamWrite> {
+        std::mem::take(&mut self.ram_watch_hits)
+    }
+
+    // pointer and timer accessor functions
+    pub fn get_pro_counter(&self) -> u16 {
+        self.pro_counter
+    }
+    pub fn set_pro_counter(&mut self, addr: u16) {
+        self.pro_counter = addr;
+    }
+    pub fn get_mem_pointer(&self) -> u16 {
+        self.mem_pointer
+    }
+    pub fn set_mem_pointer(&mut self, addr: u16) {
+        self.mem_pointer = addr;
+    }
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+    pub fn set_delay_timer(&mut self, val: u8) {
+        self.delay_timer = val;
+    }
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+    pub fn set_sound_timer(&mut self, val: u8) {
+        self.sound_timer = val;
     }
 
     // ROM loading function
-    pub fn load_rom(&mut self, loc: u16, filename: &str) {
-        print!("{:?} => ",filename);
-        let mut load_file = match File::open(filename) {
-            Ok(f) => f,
-            Err(e) => panic!("could not open file: {:?}",e)
+    pub fn load_rom(&mut self, loc: u16, filename: &Path) -> Result<usize, EmulatorError> {
+        // returns the number of bytes loaded
+        let io_error = |error| EmulatorError::Io {
+            path: filename.to_path_buf(),
+            error,
         };
+        let mut load_file = File::open(filename).map_err(io_error)?;
         let mut buf: Vec<u8> = Vec::new();
-        match load_file.read_to_end(&mut buf) {
-            Ok(_) => (),
-            Err(e) => panic!("could not open file: {:?}",e)
-        };
-        println!("{} bytes.", buf.len());
-        for i in 0..buf.len() {
-            self.set_ram(loc + i as u16, buf[i]);
+        load_file.read_to_end(&mut buf).map_err(io_error)?;
+        if loc as usize + buf.len() > self.ram.len() {
+            return Err(EmulatorError::RomTooLarge {
+                path: filename.to_path_buf(),
+                loc,
+                size: buf.len(),
+            });
         }
+        println!("{:?} => {} bytes.", filename, buf.len());
+        let loc = loc as usize;
+        self.ram[loc..loc + buf.len()].copy_from_slice(&buf);
+        Ok(buf.len())
     }
 
-    // register accessor functions
+    // register accessor functions, x is always a nibble
     pub fn get_register_data(&self, x: u8) -> Register {
         Register {
-            ..self.registers[x as usize]
+            ..self.registers[(x & 0xF) as usize]
         }
     }
     pub fn get_register(&self, x: u8) -> u8 {
-        self.registers[x as usize].value
+        self.registers[(x & 0xF) as usize].value
     }
     pub fn set_register(&mut self, x: u8, val: u8) {
-        self.registers[x as usize].value = val;
+        self.registers[(x & 0xF) as usize].value = val;
     }
 
-    // graphics accessor functions
+    // graphics accessor functions, drawing and clearing only touch the selected planes
     pub fn clear_screen(&mut self) {
-        self.display = [[false; 64]; 128];
+        for plane in 0..2 {
+            if self.planes & (1 << plane) != 0 {
+                self.display[plane] = [[false; 64]; 128];
+            }
+        }
     }
-    pub fn draw_hi(&mut self, x: usize, y: usize) -> bool {
+    pub fn draw_hi(&mut self, plane: usize, x: usize, y: usize) -> bool {
         // returns whether this draw call intersected
         if x >= 128 || y >= 64 {
             return false;
         }
-        if self.display[x][y] {
-            self.display[x][y] = false;
-            true
-        } else {
-            self.display[x][y] = true;
-            false
-        }
+        let pixel = &mut self.display[plane][x][y];
+        *pixel = !*pixel;
+        !*pixel
     }
-    pub fn draw_lo(&mut self, x: usize, y: usize) -> bool {
+    pub fn draw_lo(&mut self, plane: usize, x: usize, y: usize) -> bool {
         // returns same as draw_hi, just doubles coordinates
         if x >= 64 || y >= 32 {
             return false;
@@ -122,14 +222,9 @@ impl Emulator {
         let y = y * 2;
         let mut drew_over = false;
 
-        for xo in 0..2 as usize {
-            for yo in 0..2 as usize {
-                if self.display[x + xo][y + yo] {
-                    self.display[x + xo][y + yo] = false;
-                    drew_over = true;
-                } else {
-                    self.display[x + xo][y + yo] = true;
-                }
+        for xo in 0..2 {
+            for yo in 0..2 {
+                drew_over |= self.draw_hi(plane, x + xo, y + yo);
             }
         }
 
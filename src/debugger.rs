@@ -0,0 +1,464 @@
+use crate::error::EmulatorError;
+use crate::instruction::Instruction;
+use crate::{parse_address, Emulator};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+
+const HELP: &str = "\
+commands (numbers are decimal, or hex with 0x):
+  c, continue            resume execution
+  p, pause               pause execution
+  s, step [n]            execute n instructions (default 1)
+  sb, back [n]           undo n instructions (default 1)
+  n, next                step over a subroutine call
+  f, finish              run until the current subroutine returns
+  b, break ADDR          set a breakpoint
+  d, delete ADDR         remove a breakpoint
+  w, watch ADDR|vX|i     stop when RAM is written or a register changes
+  uw, unwatch ADDR|vX|i  remove a watch
+  i, info                list breakpoints and watches
+  r, regs                show registers, pointers, timers and the stack
+  set vX|i|pc|dt|st VAL  change a register, pointer or timer
+  m, mem ADDR [LEN]      dump RAM (default 16 bytes)
+  poke ADDR BYTE...      write bytes to RAM
+  stack [push ADDR|pop]  show or change the call stack
+  h, help                show this";
+
+// what the debugger does with the CPU between commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Running,
+    Paused,
+    StepOver { pc: u16, depth: usize }, // run until a Call returns to pc
+    StepOut { depth: usize },           // run until the stack is shallower than depth
+}
+
+// everything besides RAM the debugger can watch or change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    V(u8),
+    I,
+    Pc,
+    DelayTimer,
+    SoundTimer,
+}
+impl Target {
+    fn parse(s: &str) -> Option<Target> {
+        let s = s.to_ascii_lowercase();
+        match s.as_str() {
+            "i" => Some(Target::I),
+            "pc" => Some(Target::Pc),
+            "dt" => Some(Target::DelayTimer),
+            "st" => Some(Target::SoundTimer),
+            _ => {
+                let x = s.strip_prefix('v')?;
+                if x.len() != 1 {
+                    return None;
+                }
+                u8::from_str_radix(x, 16).ok().map(Target::V)
+            }
+        }
+    }
+    fn name(&self) -> String {
+        match self {
+            Target::V(x) => format!("V{:X}", x),
+            Target::I => String::from("I"),
+            Target::Pc => String::from("PC"),
+            Target::DelayTimer => String::from("DT"),
+            Target::SoundTimer => String::from("ST"),
+        }
+    }
+    fn get(&self, emulator: &Emulator) -> u16 {
+        match self {
+            Target::V(x) => emulator.get_register(*x) as u16,
+            Target::I => emulator.get_mem_pointer(),
+            Target::Pc => emulator.get_pro_counter(),
+            Target::DelayTimer => emulator.get_delay_timer() as u16,
+            Target::SoundTimer => emulator.get_sound_timer() as u16,
+        }
+    }
+    fn set(&self, emulator: &mut Emulator, val: u16) {
+        match self {
+            Target::V(x) => emulator.set_register(*x, val as u8),
+            Target::I => emulator.set_mem_pointer(val),
+            Target::Pc => emulator.set_pro_counter(val),
+            Target::DelayTimer => emulator.set_delay_timer(val as u8),
+            Target::SoundTimer => emulator.set_sound_timer(val as u8),
+        }
+    }
+}
+
+fn parse_value(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    };
+    parsed.map_err(|e| format!("{:?}: {}", s, e))
+}
+
+pub struct Debugger {
+    mode: RunMode,
+    breakpoints: BTreeSet<u16>,
+    register_watches: BTreeSet<Target>,
+    stop_reason: Option<String>,
+}
+
+impl Debugger {
+    pub fn new(paused: bool) -> Debugger {
+        Debugger {
+            mode: if paused { RunMode::Paused } else { RunMode::Running },
+            breakpoints: BTreeSet::new(),
+            register_watches: BTreeSet::new(),
+            stop_reason: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == RunMode::Paused
+    }
+
+    // why execution stopped since the last call, if it did
+    pub fn take_stop_reason(&mut self) -> Option<String> {
+        self.stop_reason.take()
+    }
+
+    fn stop(&mut self, reason: String) {
+        self.mode = RunMode::Paused;
+        match &mut self.stop_reason {
+            Some(reasons) => {
+                reasons.push('\n');
+                reasons.push_str(&reason);
+            }
+            None => self.stop_reason = Some(reason),
+        }
+    }
+
+    // runs one instruction unless paused, then pauses if anything asked for it
+    pub fn execute(&mut self, emulator: &mut Emulator) -> Result<bool, EmulatorError> {
+        if self.is_paused() {
+            return Ok(false);
+        }
+        let redraw = self.step(emulator)?;
+
+        let pc = emulator.get_pro_counter();
+        let depth = emulator.get_call_stack().len();
+        match self.mode {
+            RunMode::StepOver { pc: target, depth: target_depth }
+                if pc == target && depth == target_depth =>
+            {
+                self.stop(format!("stepped over call, now at {:#05X}", pc));
+            }
+            RunMode::StepOut { depth: target_depth } if depth < target_depth => {
+                self.stop(format!("returned to {:#05X}", pc));
+            }
+            _ => (),
+        }
+        if !self.is_paused() && self.breakpoints.contains(&pc) {
+            self.stop(format!("breakpoint at {:#05X}", pc));
+        }
+        Ok(redraw)
+    }
+
+    // a single instruction, checking the watches but ignoring the run mode
+    fn step(&mut self, emulator: &mut Emulator) -> Result<bool, EmulatorError> {
+        let before: Vec<(Target, u16)> = self
+            .register_watches
+            .iter()
+            .map(|target| (*target, target.get(emulator)))
+            .collect();
+
+        let pc = emulator.get_pro_counter();
+        let result = emulator.execute();
+
+        for hit in emulator.take_ram_watch_hits() {
+            self.stop(format!(
+                "{:#05X} wrote {:#05X}: {:#04X} -> {:#04X}",
+                pc, hit.addr, hit.old, hit.new
+            ));
+        }
+        for (target, old) in before {
+            let new = target.get(emulator);
+            if new != old {
+                self.stop(format!(
+                    "{:#05X} changed {}: {:#04X} -> {:#04X}",
+                    pc,
+                    target.name(),
+                    old,
+                    new
+                ));
+            }
+        }
+        if result.is_err() {
+            self.mode = RunMode::Paused;
+        }
+        result
+    }
+
+    // runs one line typed at the prompt, returning what to print
+    pub fn command(&mut self, line: &str, emulator: &mut Emulator) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((name, args)) = words.split_first() else {
+            return String::new();
+        };
+        match self.run_command(name, args, emulator) {
+            Ok(output) => output,
+            Err(e) => format!("error: {}", e),
+        }
+    }
+
+    fn run_command(
+        &mut self,
+        name: &str,
+        args: &[&str],
+        emulator: &mut Emulator,
+    ) -> Result<String, String> {
+        let addr_arg = |i: usize| -> Result<u16, String> {
+            args.get(i)
+                .ok_or_else(|| String::from("missing address"))
+                .and_then(|s| parse_address(s))
+        };
+        match name {
+            "c" | "continue" => {
+                self.mode = RunMode::Running;
+                Ok(String::from("running"))
+            }
+            "p" | "pause" => {
+                self.mode = RunMode::Paused;
+                Ok(self.registers(emulator))
+            }
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(n) => parse_value(n)?,
+                    None => 1,
+                };
+                self.mode = RunMode::Paused;
+                let mut output = String::new();
+                for _ in 0..count {
+                    if emulator.waiting_for_key() {
+                        output.push_str("waiting for a key press\n");
+                        break;
+                    }
+                    if let Err(e) = self.step(emulator) {
+                        let _ = writeln!(output, "error: {}", e);
+                        break;
+                    }
+                    if self.stop_reason.is_some() {
+                        break;
+                    }
+                }
+                if let Some(reason) = self.take_stop_reason() {
+                    let _ = writeln!(output, "{}", reason);
+                }
+                output.push_str(&self.registers(emulator));
+                Ok(output)
+            }
+            "sb" | "back" => {
+                let count = match args.first() {
+                    Some(n) => parse_value(n)?,
+                    None => 1,
+                };
+                self.mode = RunMode::Paused;
+                let undone = emulator.step_back(count as u64);
+                if undone == 0 {
+                    return Err(String::from("no history to step back into"));
+                }
+                Ok(format!(
+                    "stepped back {} instructions\n{}",
+                    undone,
+                    self.registers(emulator)
+                ))
+            }
+            "n" | "next" => {
+                let pc = emulator.get_pro_counter();
+                if let Instruction::Call(_) = current_instruction(emulator)? {
+                    self.mode = RunMode::StepOver {
+                        pc: pc.wrapping_add(2),
+                        depth: emulator.get_call_stack().len(),
+                    };
+                    Ok(format!("running to {:#05X}", pc.wrapping_add(2)))
+                } else {
+                    self.run_command("step", &[], emulator)
+                }
+            }
+            "f" | "finish" => {
+                let depth = emulator.get_call_stack().len();
+                if depth == 0 {
+                    return Err(String::from("not inside a subroutine"));
+                }
+                self.mode = RunMode::StepOut { depth };
+                Ok(String::from("running until return"))
+            }
+            "b" | "break" => {
+                let addr = addr_arg(0)?;
+                self.breakpoints.insert(addr);
+                Ok(format!("breakpoint at {:#05X}", addr))
+            }
+            "d" | "delete" => {
+                let addr = addr_arg(0)?;
+                if self.breakpoints.remove(&addr) {
+                    Ok(format!("removed breakpoint at {:#05X}", addr))
+                } else {
+                    Err(format!("no breakpoint at {:#05X}", addr))
+                }
+            }
+            "w" | "watch" | "uw" | "unwatch" => {
+                let watch = matches!(name, "w" | "watch");
+                let arg = args.first().ok_or_else(|| String::from("missing watch target"))?;
+                match Target::parse(arg) {
+                    Some(Target::V(x)) => self.watch_register(Target::V(x), watch),
+                    Some(Target::I) => self.watch_register(Target::I, watch),
+                    Some(_) => Err(String::from("only V0-VF and I can be watched")),
+                    None => {
+                        let addr = parse_address(arg)?;
+                        if watch {
+                            emulator.watch_ram(addr);
+                            Ok(format!("watching writes to {:#05X}", addr))
+                        } else {
+                            emulator.unwatch_ram(addr);
+                            Ok(format!("no longer watching {:#05X}", addr))
+                        }
+                    }
+                }
+            }
+            "i" | "info" => {
+                let mut output = String::from("breakpoints:");
+                for addr in &self.breakpoints {
+                    let _ = write!(output, " {:#05X}", addr);
+                }
+                output.push_str("\nwatches:");
+                for addr in emulator.get_ram_watches() {
+                    let _ = write!(output, " {:#05X}", addr);
+                }
+                for target in &self.register_watches {
+                    let _ = write!(output, " {}", target.name());
+                }
+                Ok(output)
+            }
+            "r" | "regs" => Ok(self.registers(emulator)),
+            "set" => {
+                let (Some(target), Some(val)) = (args.first(), args.get(1)) else {
+                    return Err(String::from("usage: set vX|i|pc|dt|st VAL"));
+                };
+                let target = Target::parse(target)
+                    .ok_or_else(|| format!("{:?} is not a register, pointer or timer", target))?;
+                target.set(emulator, parse_value(val)?);
+                Ok(self.registers(emulator))
+            }
+            "m" | "mem" => {
+                let addr = addr_arg(0)?;
+                let len = match args.get(1) {
+                    Some(n) => parse_value(n)?,
+                    None => 16,
+                };
+                let mut output = String::new();
+                for row in (addr as u32..addr as u32 + len as u32).step_by(16) {
+                    let _ = write!(output, "{:#05X}:", row);
+                    for a in row..(row + 16).min(addr as u32 + len as u32) {
+                        match emulator.get_ram(a as u16) {
+                            Ok(byte) => {
+                                let _ = write!(output, " {:02X}", byte);
+                            }
+                            Err(_) => output.push_str(" --"),
+                        }
+                    }
+                    output.push('\n');
+                }
+                Ok(output.trim_end().to_string())
+            }
+            "poke" => {
+                let addr = addr_arg(0)?;
+                if args.len() < 2 {
+                    return Err(String::from("usage: poke ADDR BYTE..."));
+                }
+                for (i, byte) in args[1..].iter().enumerate() {
+                    let byte = parse_value(byte)?;
+                    emulator
+                        .set_ram(addr.wrapping_add(i as u16), byte as u8)
+                        .map_err(|e| e.to_string())?;
+                }
+                // our own writes don't trip the watches
+                emulator.take_ram_watch_hits();
+                Ok(format!("wrote {} bytes at {:#05X}", args.len() - 1, addr))
+            }
+            "stack" => match args.first() {
+                None => Ok(format_stack(emulator)),
+                Some(&"push") => {
+                    if !emulator.push_callstack(addr_arg(1)?) {
+                        return Err(String::from("the call stack is full"));
+                    }
+                    Ok(format_stack(emulator))
+                }
+                Some(&"pop") => {
+                    emulator
+                        .pop_callstack()
+                        .ok_or_else(|| String::from("the call stack is empty"))?;
+                    Ok(format_stack(emulator))
+                }
+                Some(other) => Err(format!("unknown stack command {:?}", other)),
+            },
+            "h" | "help" => Ok(String::from(HELP)),
+            _ => Err(format!("unknown command {:?}, try `help`", name)),
+        }
+    }
+
+    fn watch_register(&mut self, target: Target, watch: bool) -> Result<String, String> {
+        if watch {
+            self.register_watches.insert(target);
+            Ok(format!("watching {}", target.name()))
+        } else {
+            self.register_watches.remove(&target);
+            Ok(format!("no longer watching {}", target.name()))
+        }
+    }
+
+    // a summary of the machine, with the instruction about to run
+    pub fn registers(&self, emulator: &Emulator) -> String {
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            "PC {:#05X}  I {:#05X}  DT {:3}  ST {:3}  SP {}",
+            emulator.get_pro_counter(),
+            emulator.get_mem_pointer(),
+            emulator.get_delay_timer(),
+            emulator.get_sound_timer(),
+            emulator.get_call_stack().len()
+        );
+        for row in 0..2u8 {
+            for x in row * 8..row * 8 + 8 {
+                let _ = write!(output, "V{:X} {:02X}  ", x, emulator.get_register(x));
+            }
+            output.truncate(output.trim_end().len());
+            output.push('\n');
+        }
+        let pc = emulator.get_pro_counter();
+        match (emulator.get_ram(pc), emulator.get_ram(pc.wrapping_add(1))) {
+            (Ok(msb), Ok(lsb)) => {
+                let inst = Instruction::decode(u16::from_be_bytes([msb, lsb]));
+                let _ = write!(output, "{:#05X}: {:02X}{:02X}  {:?}", pc, msb, lsb, inst);
+            }
+            _ => {
+                let _ = write!(output, "{:#05X}: out of RAM", pc);
+            }
+        }
+        output
+    }
+}
+
+fn current_instruction(emulator: &Emulator) -> Result<Instruction, String> {
+    let pc = emulator.get_pro_counter();
+    let msb = emulator.get_ram(pc).map_err(|e| e.to_string())?;
+    let lsb = emulator.get_ram(pc.wrapping_add(1)).map_err(|e| e.to_string())?;
+    Ok(Instruction::decode(u16::from_be_bytes([msb, lsb])))
+}
+
+fn format_stack(emulator: &Emulator) -> String {
+    let stack = emulator.get_call_stack();
+    if stack.is_empty() {
+        return String::from("stack: empty");
+    }
+    let mut output = String::from("stack (innermost last):");
+    for addr in stack {
+        let _ = write!(output, " {:#05X}", addr);
+    }
+    output
+}
@@ -0,0 +1,355 @@
+use crate::instruction::{Instruction, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+// which assembly dialect to print
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    #[default]
+    Classic, // Cowgod-style mnemonics (CLS, LD V0, 0x05, DRW ...)
+    Octo,    // Octo statements (clear, v0 := 0x05, sprite ...)
+}
+impl FromStr for Syntax {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "classic" => Ok(Syntax::Classic),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!("unknown syntax {:?} (expected classic or octo)", s)),
+        }
+    }
+}
+
+// what a label marks, which decides its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Subroutine, // target of a Call
+    Code,       // target of a Jump or skip
+    Data,       // target of an I load
+}
+
+pub struct Disassembly {
+    origin: u16,
+    rom: Vec<u8>,
+    code: BTreeSet<u16>, // addresses of instructions reached from the entry point
+    labels: BTreeMap<u16, LabelKind>,
+}
+
+impl Disassembly {
+    // traces every path from origin to separate instructions from data
+    pub fn new(rom: &[u8], origin: u16) -> Disassembly {
+        let mut ret = Disassembly {
+            origin,
+            rom: rom.to_vec(),
+            code: BTreeSet::new(),
+            labels: BTreeMap::new(),
+        };
+        let mut pending = vec![origin];
+        while let Some(pc) = pending.pop() {
+            if ret.code.contains(&pc) {
+                continue;
+            }
+            let Some((msb, lsb)) = ret.word(pc) else { continue };
+            let inst = Instruction::decode(u16::from_be_bytes([msb, lsb]));
+            if let Instruction::Invalid(_) = inst {
+                continue;
+            }
+            if inst == Instruction::LongPointer && ret.word(pc.wrapping_add(2)).is_none() {
+                // the address word is cut off by the end of the ROM
+                continue;
+            }
+            ret.code.insert(pc);
+            let next = pc.wrapping_add(inst.size());
+            use Instruction::*;
+            match inst {
+                Jump(addr) => {
+                    ret.add_label(addr, LabelKind::Code);
+                    pending.push(addr);
+                }
+                JumpPlus { addr, .. } => {
+                    // computed jump, the best guess is a jump table at addr
+                    ret.add_label(addr, LabelKind::Code);
+                    pending.push(addr);
+                }
+                Call(addr) => {
+                    ret.add_label(addr, LabelKind::Subroutine);
+                    pending.push(addr);
+                    pending.push(next);
+                }
+                Return => (),
+                SkipIfEqual { .. } | SkipIfUnequal { .. } | SkipIfKey(_) | SkipIfNotKey(_) => {
+                    // a skip steps over a whole long I load
+                    let skipped = match ret.word(next) {
+                        Some((msb, lsb)) => Instruction::decode(u16::from_be_bytes([msb, lsb])).size(),
+                        None => 2,
+                    };
+                    pending.push(next);
+                    pending.push(next.wrapping_add(skipped));
+                }
+                SetPointer(addr) => {
+                    ret.add_label(addr, LabelKind::Data);
+                    pending.push(next);
+                }
+                LongPointer => {
+                    if let Some(addr) = ret.long_operand(pc) {
+                        ret.add_label(addr, LabelKind::Data);
+                    }
+                    pending.push(next);
+                }
+                _ => pending.push(next),
+            }
+        }
+        // labels outside the ROM would point at nothing we print
+        let end = ret.end();
+        ret.labels.retain(|addr, _| *addr >= origin && (*addr as u32) < end);
+        ret
+    }
+
+    fn end(&self) -> u32 {
+        self.origin as u32 + self.rom.len() as u32
+    }
+
+    fn word(&self, addr: u16) -> Option<(u8, u8)> {
+        let offset = (addr as usize).checked_sub(self.origin as usize)?;
+        Some((*self.rom.get(offset)?, *self.rom.get(offset + 1)?))
+    }
+
+    // the address word after an F000
+    fn long_operand(&self, pc: u16) -> Option<u16> {
+        self.word(pc.wrapping_add(2)).map(|(msb, lsb)| u16::from_be_bytes([msb, lsb]))
+    }
+
+    fn add_label(&mut self, addr: u16, kind: LabelKind) {
+        let entry = self.labels.entry(addr).or_insert(kind);
+        // a subroutine name wins over a jump target, which wins over data
+        *entry = (*entry).min(kind);
+    }
+
+    fn label(&self, addr: u16) -> Option<String> {
+        let kind = self.labels.get(&addr)?;
+        let prefix = match kind {
+            LabelKind::Subroutine => "sub",
+            LabelKind::Code => "label",
+            LabelKind::Data => "data",
+        };
+        Some(format!("{}_{:03X}", prefix, addr))
+    }
+
+    // an address operand, by label when there is one
+    fn target(&self, addr: u16) -> String {
+        self.label(addr).unwrap_or_else(|| format!("0x{:03X}", addr))
+    }
+
+    // whether a label points past the first byte of the size bytes at addr
+    fn label_inside(&self, addr: u16, size: u16) -> bool {
+        let addr = addr as u32;
+        self.labels.keys().any(|label| (addr + 1..addr + size as u32).contains(&(*label as u32)))
+    }
+
+    pub fn is_code(&self, addr: u16) -> bool {
+        self.code.contains(&addr)
+    }
+
+    pub fn print(&self, syntax: Syntax) -> String {
+        let comment = match syntax {
+            Syntax::Classic => ";",
+            Syntax::Octo => "#",
+        };
+        let mut out = String::new();
+        if self.origin != 0x200 {
+            match syntax {
+                Syntax::Classic => {
+                    let _ = writeln!(out, "ORG 0x{:03X}", self.origin);
+                }
+                Syntax::Octo => {
+                    let _ = writeln!(out, ":org 0x{:03X}", self.origin);
+                }
+            }
+        }
+
+        let end = self.end();
+        let mut addr = self.origin as u32;
+        while addr < end {
+            let a = addr as u16;
+            if let Some(label) = self.label(a) {
+                match syntax {
+                    Syntax::Classic => {
+                        let _ = writeln!(out, "{}:", label);
+                    }
+                    Syntax::Octo => {
+                        let _ = writeln!(out, ": {}", label);
+                    }
+                }
+            }
+            let (msb, lsb) = self.word(a).unwrap_or_default();
+            let inst = Instruction::decode(u16::from_be_bytes([msb, lsb]));
+            let text = match syntax {
+                Syntax::Classic => self.classic(&inst, a),
+                Syntax::Octo => self.octo(&inst, a),
+            };
+            // an instruction with a label inside it goes out as bytes, so the label has a line
+            let overlapped = self.is_code(a) && self.label_inside(a, inst.size());
+            if self.is_code(a) && !overlapped {
+                let mut bytes = format!("{:02X} {:02X}", msb, lsb);
+                if inst == Instruction::LongPointer {
+                    let (msb, lsb) = self.word(a.wrapping_add(2)).unwrap_or_default();
+                    let _ = write!(bytes, " {:02X} {:02X}", msb, lsb);
+                }
+                let _ = writeln!(out, "    {:<24}{} {:03X}: {}", text, comment, a, bytes);
+                addr += inst.size() as u32;
+                continue;
+            }
+
+            // a run of data up to the next label or instruction, 8 bytes a line
+            let mut bytes = Vec::new();
+            while addr < end && bytes.len() < 8 {
+                let b = addr as u16;
+                if !bytes.is_empty() && (self.labels.contains_key(&b) || self.is_code(b)) {
+                    break;
+                }
+                bytes.push(self.rom[(addr - self.origin as u32) as usize]);
+                addr += 1;
+            }
+            let values: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+            let data = match syntax {
+                Syntax::Classic => format!("DB {}", values.join(", ")),
+                Syntax::Octo => values.join(" "),
+            };
+            if overlapped {
+                let _ = writeln!(out, "    {:<24}{} {:03X}: {}", data, comment, a, text);
+            } else {
+                let _ = writeln!(out, "    {:<24}{} {:03X}", data, comment, a);
+            }
+        }
+        out
+    }
+
+    fn classic(&self, inst: &Instruction, addr: u16) -> String {
+        use Instruction::*;
+        match inst {
+            ClearScreen => String::from("CLS"),
+            Jump(addr) => format!("JP {}", self.target(*addr)),
+            JumpPlus { addr, .. } => format!("JP V0, {}", self.target(*addr)),
+            Call(addr) => format!("CALL {}", self.target(*addr)),
+            Return => String::from("RET"),
+            SkipIfEqual { reg, comp } => format!("SE V{:X}, {}", reg, classic_value(comp)),
+            SkipIfUnequal { reg, comp } => format!("SNE V{:X}, {}", reg, classic_value(comp)),
+            SkipIfKey(x) => format!("SKP V{:X}", x),
+            SkipIfNotKey(x) => format!("SKNP V{:X}", x),
+            KeyBlock(x) => format!("LD V{:X}, K", x),
+            Load { reg, value } => format!("LD V{:X}, {}", reg, classic_value(value)),
+            AddInPlace { reg, byte } => format!("ADD V{:X}, 0x{:02X}", reg, byte),
+            Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            SubReverse { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            SetPointer(addr) => format!("LD I, {}", self.target(*addr)),
+            AddPointer(x) => format!("ADD I, V{:X}", x),
+            Random { x, byte } => format!("RND V{:X}, 0x{:02X}", x, byte),
+            HighResolution(true) => String::from("HIGH"),
+            HighResolution(false) => String::from("LOW"),
+            Draw { x, y, byte_count } => format!("DRW V{:X}, V{:X}, {}", x, y, byte_count),
+            DrawLarge { x, y } => format!("DRW V{:X}, V{:X}, 0", x, y),
+            ScrollRight => String::from("SCR"),
+            ScrollLeft => String::from("SCL"),
+            ScrollDown(n) => format!("SCD {}", n),
+            GetTimer(x) => format!("LD V{:X}, DT", x),
+            SetTimer(x) => format!("LD DT, V{:X}", x),
+            SetSound(x) => format!("LD ST, V{:X}", x),
+            GetDigit(x) => format!("LD F, V{:X}", x),
+            GetLargeDigit(x) => format!("LD HF, V{:X}", x),
+            StoreDecimal(x) => format!("LD B, V{:X}", x),
+            StoreRegisters(x) => format!("LD [I], V{:X}", x),
+            LoadRegisters(x) => format!("LD V{:X}, [I]", x),
+            StoreRegistersRPL(x) => format!("LD R, V{:X}", x),
+            LoadRegistersRPL(x) => format!("LD V{:X}, R", x),
+            ScrollUp(n) => format!("SCU {}", n),
+            SelectPlanes(n) => format!("PLANE {}", n),
+            SaveRange { x, y } => format!("SAVE V{:X}, V{:X}", x, y),
+            LoadRange { x, y } => format!("LOAD V{:X}, V{:X}", x, y),
+            LongPointer => format!("LD I, LONG {}", self.target(self.long_operand(addr).unwrap_or_default())),
+            LoadAudio => String::from("AUDIO"),
+            SetPitch(x) => format!("PITCH V{:X}", x),
+            Invalid(_) => String::from("???"),
+        }
+    }
+
+    fn octo(&self, inst: &Instruction, addr: u16) -> String {
+        use Instruction::*;
+        // Octo's conditionals say when the next statement runs, skips say when it doesn't
+        match inst {
+            ClearScreen => String::from("clear"),
+            Jump(addr) => format!("jump {}", self.target(*addr)),
+            JumpPlus { addr, .. } => format!("jump0 {}", self.target(*addr)),
+            Call(addr) => format!(":call {}", self.target(*addr)),
+            Return => String::from("return"),
+            SkipIfEqual { reg, comp } => format!("if v{:x} != {} then", reg, octo_value(comp)),
+            SkipIfUnequal { reg, comp } => format!("if v{:x} == {} then", reg, octo_value(comp)),
+            SkipIfKey(x) => format!("if v{:x} -key then", x),
+            SkipIfNotKey(x) => format!("if v{:x} key then", x),
+            KeyBlock(x) => format!("v{:x} := key", x),
+            Load { reg, value } => format!("v{:x} := {}", reg, octo_value(value)),
+            AddInPlace { reg, byte } => format!("v{:x} += 0x{:02X}", reg, byte),
+            Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Add { x, y } => format!("v{:x} += v{:x}", x, y),
+            Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            SubReverse { x, y } => format!("v{:x} =- v{:x}", x, y),
+            ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            SetPointer(addr) => format!("i := {}", self.target(*addr)),
+            AddPointer(x) => format!("i += v{:x}", x),
+            Random { x, byte } => format!("v{:x} := random 0x{:02X}", x, byte),
+            HighResolution(true) => String::from("hires"),
+            HighResolution(false) => String::from("lores"),
+            Draw { x, y, byte_count } => format!("sprite v{:x} v{:x} {}", x, y, byte_count),
+            DrawLarge { x, y } => format!("sprite v{:x} v{:x} 0", x, y),
+            ScrollRight => String::from("scroll-right"),
+            ScrollLeft => String::from("scroll-left"),
+            ScrollDown(n) => format!("scroll-down {}", n),
+            GetTimer(x) => format!("v{:x} := delay", x),
+            SetTimer(x) => format!("delay := v{:x}", x),
+            SetSound(x) => format!("buzzer := v{:x}", x),
+            GetDigit(x) => format!("i := hex v{:x}", x),
+            GetLargeDigit(x) => format!("i := bighex v{:x}", x),
+            StoreDecimal(x) => format!("bcd v{:x}", x),
+            StoreRegisters(x) => format!("save v{:x}", x),
+            LoadRegisters(x) => format!("load v{:x}", x),
+            StoreRegistersRPL(x) => format!("saveflags v{:x}", x),
+            LoadRegistersRPL(x) => format!("loadflags v{:x}", x),
+            ScrollUp(n) => format!("scroll-up {}", n),
+            SelectPlanes(n) => format!("plane {}", n),
+            SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            LongPointer => format!("i := long {}", self.target(self.long_operand(addr).unwrap_or_default())),
+            LoadAudio => String::from("audio"),
+            SetPitch(x) => format!("pitch := v{:x}", x),
+            Invalid(_) => String::from("# invalid"),
+        }
+    }
+}
+
+fn classic_value(value: &Value) -> String {
+    match value {
+        Value::Register(reg) => format!("V{:X}", reg),
+        Value::Byte(byte) => format!("0x{:02X}", byte),
+    }
+}
+
+fn octo_value(value: &Value) -> String {
+    match value {
+        Value::Register(reg) => format!("v{:x}", reg),
+        Value::Byte(byte) => format!("0x{:02X}", byte),
+    }
+}
+
+// shorthand for tools that just want the text
+pub fn disassemble(rom: &[u8], origin: u16, syntax: Syntax) -> String {
+    Disassembly::new(rom, origin).print(syntax)
+}
@@ -0,0 +1,78 @@
+use crate::components::Resolution;
+use crate::quirks::Platform;
+use crate::Emulator;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+// helpers for getting the machine state out in formats other tools can read
+
+impl Emulator {
+    // plain (ASCII) PBM of the full 128x64 display buffer, 1 = lit on any plane
+    pub fn display_pbm(&self) -> String {
+        let mut out = String::from("P1\n128 64\n");
+        for y in 0..64 {
+            let row: Vec<&str> = (0..128)
+                .map(|x| if self.pixel(x, y) != 0 { "1" } else { "0" })
+                .collect();
+            out.push_str(&row.join(" "));
+            out.push('\n');
+        }
+        out
+    }
+
+    // greyscale PNG of the same buffer, 1-bit with lit pixels white,
+    // or 2-bit on XO-CHIP with each plane combination its own shade
+    pub fn write_display_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let bits = match self.platform {
+            Platform::Schip => 1,
+            Platform::XoChip => 2,
+        };
+        let mut encoder = png::Encoder::new(writer, 128, 64);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(if bits == 1 { png::BitDepth::One } else { png::BitDepth::Two });
+        let mut data = Vec::with_capacity(16 * bits * 64);
+        for y in 0..64 {
+            let mut packed = 0u8;
+            for x in 0..128 {
+                let value = if bits == 1 { self.pixel(x, y).min(1) } else { self.pixel(x, y) };
+                packed = packed << bits | value;
+                if (x + 1) * bits % 8 == 0 {
+                    data.push(packed);
+                    packed = 0;
+                }
+            }
+        }
+        let mut png_writer = encoder.write_header()?;
+        png_writer.write_image_data(&data)?;
+        Ok(())
+    }
+
+    // registers, pointers, timers, stack and memory as a JSON object
+    pub fn state_json(&self) -> String {
+        fn list<T: ToString>(values: impl Iterator<Item = T>) -> String {
+            let values: Vec<String> = values.map(|v| v.to_string()).collect();
+            format!("[{}]", values.join(","))
+        }
+        let mut out = String::from("{\n");
+        let _ = writeln!(out, "  \"platform\": \"{}\",", self.platform);
+        let _ = writeln!(out, "  \"pc\": {},", self.pro_counter);
+        let _ = writeln!(out, "  \"i\": {},", self.mem_pointer);
+        let _ = writeln!(out, "  \"delay_timer\": {},", self.delay_timer);
+        let _ = writeln!(out, "  \"sound_timer\": {},", self.sound_timer);
+        let resolution = match self.resolution_mode {
+            Resolution::High => "high",
+            Resolution::Low => "low",
+        };
+        let _ = writeln!(out, "  \"resolution\": \"{}\",", resolution);
+        let _ = writeln!(
+            out,
+            "  \"registers\": {},",
+            list(self.registers.iter().map(|r| r.value))
+        );
+        let _ = writeln!(out, "  \"call_stack\": {},", list(self.call_stack.iter()));
+        let _ = writeln!(out, "  \"rpl\": {},", list(self.rpl_flags().iter()));
+        let _ = writeln!(out, "  \"ram\": {}", list(self.ram.iter()));
+        out.push_str("}\n");
+        out
+    }
+}
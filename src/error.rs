@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum EmulatorError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    }, // a ROM or font file couldn't be read
+    RomTooLarge {
+        path: PathBuf,
+        loc: u16,
+        size: usize,
+    }, // the file doesn't fit in RAM at loc
+    StackUnderflow {
+        pc: u16,
+    }, // returned with nothing on the call stack
+    StackOverflow {
+        pc: u16,
+    }, // called with a full call stack
+    MemoryOutOfBounds {
+        addr: usize,
+    }, // read or wrote past the end of RAM
+    RplOutOfBounds {
+        index: u8,
+    }, // touched an RPL flag that doesn't exist
+    InvalidOpcode {
+        pc: u16,
+        opcode: u16,
+    }, // fetched something that isn't an instruction
+    StateVersion {
+        version: u16,
+    }, // a save state written by a different version of the format
+    StateRom {
+        hash: u64,
+    }, // a save state of a different ROM from the one loaded
+    CorruptState {
+        reason: String,
+    }, // a save state that doesn't read back
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use EmulatorError::*;
+        match self {
+            Io { path, error } => write!(f, "could not read {:?}: {}", path, error),
+            RomTooLarge { path, loc, size } => write!(
+                f,
+                "{:?} is {} bytes, which doesn't fit in RAM at {:#05X}",
+                path, size, loc
+            ),
+            StackUnderflow { pc } => write!(f, "stack underflow at {:#05X}", pc),
+            StackOverflow { pc } => write!(f, "stack overflow at {:#05X}", pc),
+            MemoryOutOfBounds { addr } => write!(f, "memory access out of bounds at {:#06X}", addr),
+            RplOutOfBounds { index } => write!(f, "RPL flag {} does not exist", index),
+            InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode {:04X} at {:#05X}", opcode, pc)
+            }
+            StateVersion { version } => write!(
+                f,
+                "save state is version {}, only version {} can be loaded",
+                version,
+                crate::savestate::STATE_VERSION
+            ),
+            StateRom { hash } => write!(
+                f,
+                "save state is of another ROM (hash {:016x}) than the one loaded",
+                hash
+            ),
+            CorruptState { reason } => write!(f, "bad save state: {}", reason),
+        }
+    }
+}
+
+impl Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmulatorError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
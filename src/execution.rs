@@ -1,25 +1,39 @@
 use crate::components::Resolution;
-use crate::instruction::{Instruction, Value};
+use crate::error::EmulatorError;
+use crate::font;
+use crate::instruction::Instruction;
+use crate::quirks::{DisplayWait, MemoryIncrement, Platform, SpriteEdges};
 use crate::Emulator;
-use rand;
 
 const DEBUG: bool = false;
 
 impl Emulator {
-    pub fn execute(&mut self) -> bool {
+    pub fn execute(&mut self) -> Result<bool, EmulatorError> {
         // returns a bool for redrawing
+        self.begin_rewind_step();
+        let result = self.execute_instruction();
+        self.end_rewind_step();
+        result
+    }
+
+    // one instruction, without the rewind bookkeeping around it
+    pub(crate) fn execute_instruction(&mut self) -> Result<bool, EmulatorError> {
         let mut redraw = false;
 
-        let msb = self.get_ram(self.pro_counter);
-        let lsb = self.get_ram(self.pro_counter + 1);
-        self.pro_counter += 2;
+        let pc = self.pro_counter;
+        let msb = self.get_ram(pc)?;
+        let lsb = self.get_ram(pc.wrapping_add(1))?;
+        self.pro_counter = pc.wrapping_add(2);
 
-        let inst = Instruction::from(&self, msb, lsb);
+        let inst = Instruction::decode(u16::from_be_bytes([msb, lsb]));
+        if inst.is_xo_chip() && self.platform != Platform::XoChip {
+            return Err(EmulatorError::InvalidOpcode { pc, opcode: inst.encode() });
+        }
         // this is just some debug code
         if DEBUG {
             match inst {
             Instruction::Jump(_) => (),
-            Instruction::JumpPlus {ref addr, ref x} => (),
+            Instruction::JumpPlus { .. } => (),
             _ => println!("{:?}",inst)
             };
         }
@@ -36,108 +50,126 @@ impl Emulator {
                     self.pro_counter = addr;
                 }
                 JumpPlus { addr, x } => {
-                    let result = addr + (x.value as u16);
+                    let result = if self.quirks.jump_uses_vx {
+                        addr + (self.get_register(x) as u16)
+                    } else {
+                        addr + (self.get_register(0) as u16)
+                    };
                     self.pro_counter = result;
                 }
 
                 Call(addr) => {
-                    self.push_callstack(self.pro_counter);
+                    if !self.push_callstack(self.pro_counter) {
+                        return Err(EmulatorError::StackOverflow { pc });
+                    }
                     self.pro_counter = addr;
                 }
                 Return => {
-                    self.pro_counter = self.pop_callstack();
+                    self.pro_counter = self
+                        .pop_callstack()
+                        .ok_or(EmulatorError::StackUnderflow { pc })?;
                 }
 
                 SkipIfEqual { reg, comp } => {
-                    let x = reg.value;
-                    let y = comp.unwrap();
+                    let x = self.get_register(reg);
+                    let y = comp.resolve(self);
                     if x == y {
-                        self.pro_counter += 2;
+                        self.skip();
                     }
                 }
                 SkipIfUnequal { reg, comp } => {
-                    let x = reg.value;
-                    let y = comp.unwrap();
+                    let x = self.get_register(reg);
+                    let y = comp.resolve(self);
                     if x != y {
-                        self.pro_counter += 2;
+                        self.skip();
                     }
                 }
 
                 SkipIfKey(reg) => {
-                    let x = reg.value as usize;
+                    let x = (self.get_register(reg) & 0xF) as usize;
                     if self.key_handler.keys[x] {
-                        self.pro_counter += 2;
+                        self.skip();
                     }
                 }
                 SkipIfNotKey(reg) => {
-                    let x = reg.value as usize;
+                    let x = (self.get_register(reg) & 0xF) as usize;
                     if !self.key_handler.keys[x] {
-                        self.pro_counter += 2;
+                        self.skip();
                     }
                 }
                 KeyBlock(reg) => {
-                    let x = reg.loc;
-                    self.key_handler.key_hold = x;
+                    self.key_handler.start_wait(reg);
                 }
 
                 Load { reg, value } => {
-                    let x = reg.loc;
-                    let y = value.unwrap();
-                    self.set_register(x, y);
+                    let y = value.resolve(self);
+                    self.set_register(reg, y);
                 }
 
                 AddInPlace { reg, byte } => {
-                    self.set_register(reg.loc, reg.value.overflowing_add(byte).0);
+                    self.set_register(reg, self.get_register(reg).wrapping_add(byte));
                 }
 
                 Or { x, y } => {
-                    let (x_loc, x_val, y_val) = (x.loc, x.value, y.value);
+                    let (x_loc, x_val, y_val) = (x, self.get_register(x), self.get_register(y));
                     self.set_register(x_loc, x_val | y_val);
+                    if self.quirks.logic_resets_vf {
+                        self.set_register(0xF, 0);
+                    }
                 }
                 And { x, y } => {
-                    let (x_loc, x_val, y_val) = (x.loc, x.value, y.value);
+                    let (x_loc, x_val, y_val) = (x, self.get_register(x), self.get_register(y));
                     self.set_register(x_loc, x_val & y_val);
+                    if self.quirks.logic_resets_vf {
+                        self.set_register(0xF, 0);
+                    }
                 }
                 Xor { x, y } => {
-                    let (x_loc, x_val, y_val) = (x.loc, x.value, y.value);
+                    let (x_loc, x_val, y_val) = (x, self.get_register(x), self.get_register(y));
                     self.set_register(x_loc, x_val ^ y_val);
+                    if self.quirks.logic_resets_vf {
+                        self.set_register(0xF, 0);
+                    }
                 }
 
                 Add { x, y } => {
-                    let (x_loc, x_val, y_val) = (x.loc, x.value, y.value);
+                    let (x_loc, x_val, y_val) = (x, self.get_register(x), self.get_register(y));
                     let (result, overflow) = x_val.overflowing_add(y_val);
-                    self.set_register(0xF, u8::from(overflow));
-                    self.set_register(x_loc, result);
+                    self.set_result_and_flag(x_loc, result, u8::from(overflow));
                 }
                 Sub { x, y } => {
-                    let (x_loc, x_val, y_val) = (x.loc, x.value, y.value);
+                    let (x_loc, x_val, y_val) = (x, self.get_register(x), self.get_register(y));
                     let (result, overflow) = x_val.overflowing_sub(y_val);
-                    self.set_register(0xF, u8::from(!overflow));
-                    self.set_register(x_loc, result);
+                    self.set_result_and_flag(x_loc, result, u8::from(!overflow));
+                }
+                SubReverse { x, y } => {
+                    let (x_loc, x_val, y_val) = (x, self.get_register(x), self.get_register(y));
+                    let (result, overflow) = y_val.overflowing_sub(x_val);
+                    self.set_result_and_flag(x_loc, result, u8::from(!overflow));
                 }
-                ShiftRight(x) => {
-                    let (x_loc, x_val) = (x.loc, x.value);
+                ShiftRight { x, y } => {
+                    let x_loc = x;
+                    let x_val = self.get_register(if self.quirks.shift_uses_vy { y } else { x });
                     let (result, shift_bit) = (x_val >> 1, x_val & 1);
-                    self.set_register(0xF, shift_bit);
-                    self.set_register(x_loc, result);
+                    self.set_result_and_flag(x_loc, result, shift_bit);
                 }
-                ShiftLeft(x) => {
-                    let (x_loc, x_val) = (x.loc, x.value);
+                ShiftLeft { x, y } => {
+                    let x_loc = x;
+                    let x_val = self.get_register(if self.quirks.shift_uses_vy { y } else { x });
                     let (result, shift_bit) = (x_val << 1, (x_val & 0x80) >> 7);
-                    self.set_register(0xF, shift_bit);
-                    self.set_register(x_loc, result);
+                    self.set_result_and_flag(x_loc, result, shift_bit);
                 }
 
                 SetPointer(addr) => {
                     self.mem_pointer = addr;
                 }
                 AddPointer(x) => {
-                    self.mem_pointer += x.value as u16;
+                    self.mem_pointer = self.mem_pointer.wrapping_add(self.get_register(x) as u16);
                 }
 
                 Random { x, byte } => {
-                    let random: u8 = rand::random();
-                    self.set_register(x.loc, random & byte);
+                    let random = self.rng.next_byte(&self.ram);
+                    self.set_register(x, random & byte);
                 }
 
                 HighResolution(case) => {
@@ -145,189 +177,287 @@ impl Emulator {
                     self.resolution_mode = Resolution::from(case);
                 }
                 Draw { x, y, byte_count } => {
-                    use helpers;
                     redraw = true;
+                    let (x, y) = (self.get_register(x), self.get_register(y));
                     let mut collision = false;
-                    let bytes =
-                        self.get_ram_slice(self.mem_pointer, self.mem_pointer + byte_count as u16);
-                    let sprite = helpers::load_sprite(bytes);
-                    match self.resolution_mode {
-                        Resolution::Low => {
-                            // low resolution draw
-                            let (x, y) = (x.value as usize % 64, y.value as usize % 32);
-                            for x_o in 0..8 as usize {
-                                for y_o in 0..byte_count as usize {
-                                    let x_pos = (x_o + x) % 64;
-                                    let y_pos = (y_o + y) % 32;
-                                    if sprite[y_o][x_o] {
-                                        let collide_once = self.draw_lo(x_pos, y_pos);
-                                        collision =
-                                            if collision { collision } else { collide_once };
-                                    }
-                                }
-                            }
-                        }
-                        Resolution::High => {
-                            // high resolution draw
-                            let (x, y) = (x.value as usize % 128, y.value as usize % 64);
-                            for x_o in 0..8 as usize {
-                                for y_o in 0..byte_count as usize {
-                                    let x_pos = (x_o + x) % 128;
-                                    let y_pos = (y_o + y) % 64;
-                                    if sprite[y_o][x_o] {
-                                        let collide_once = self.draw_hi(x_pos, y_pos);
-                                        collision =
-                                            if collision { collision } else { collide_once };
-                                    }
-                                }
-                            }
-                        }
+                    // with both planes selected, the second plane's sprite follows the first's
+                    let mut addr = self.mem_pointer as usize;
+                    for plane in self.selected_planes() {
+                        let sprite = helpers::load_sprite(self.get_ram_bytes(addr, byte_count as usize)?);
+                        collision |= self.draw_sprite(plane, x, y, &sprite);
+                        addr += byte_count as usize;
                     }
                     self.set_register(0xF, u8::from(collision));
+                    self.vblank_wait = self.draw_waits();
                 }
                 DrawLarge { x, y } => {
-                    use helpers;
-                    let (x, y) = match self.resolution_mode {
-                        Resolution::High => (x.value as usize % 128, y.value as usize % 64),
-                        Resolution::Low => (x.value as usize % 64, y.value as usize % 32)
-                    };
                     redraw = true;
+                    let (x, y) = (self.get_register(x), self.get_register(y));
                     let mut collision = false;
-                    let bytes = self.get_ram_slice(self.mem_pointer, self.mem_pointer + 32);
-                    let sprite = helpers::load_large_sprite(bytes);
-                    for x_o in 0..16 as usize {
-                        for y_o in 0..16 as usize {
-                            let x_pos = x_o + x;
-                            let y_pos = y_o + y;
-                            if sprite[y_o][x_o] {
-                                let collide_once = match self.resolution_mode {
-                                    Resolution::High => self.draw_hi(x_pos, y_pos),
-                                    Resolution::Low => self.draw_lo(x_pos,y_pos)
-                                };
-                                collision = if collision { collision } else { collide_once };
-                            }
-                        }
+                    let mut addr = self.mem_pointer as usize;
+                    for plane in self.selected_planes() {
+                        let bytes = self.get_ram_bytes(addr, 32)?.try_into().unwrap();
+                        let sprite = helpers::load_large_sprite(bytes);
+                        collision |= self.draw_sprite(plane, x, y, &sprite);
+                        addr += 32;
                     }
                     self.set_register(0xF, u8::from(collision));
+                    self.vblank_wait = self.draw_waits();
                 }
                 ScrollRight => {
-                    for x in (0..124).rev() {
-                        let temp = self.display[x];
-                        self.display[x + 4] = temp;
-                        self.display[x] = [false; 64];
-                    }
+                    self.scroll(self.scroll_distance(4) as isize, 0);
+                    redraw = true;
                 }
                 ScrollLeft => {
-                    for x in 4..128 {
-                        let temp = self.display[x];
-                        self.display[x - 4] = temp;
-                        self.display[x] = [false; 64];
-                    }
+                    self.scroll(-(self.scroll_distance(4) as isize), 0);
+                    redraw = true;
                 }
                 ScrollDown(pixels) => {
-                    let pixels = pixels as usize;
-                    for y in (0..64 - pixels).rev() {
-                        for x in 0..128 as usize {
-                            let temp = self.display[x][y];
-                            self.display[x][y + pixels] = temp;
-                            self.display[x][y] = false;
-                        }
-                    }
+                    self.scroll(0, self.scroll_distance(pixels) as isize);
+                    redraw = true;
+                }
+                ScrollUp(pixels) => {
+                    self.scroll(0, -(self.scroll_distance(pixels) as isize));
+                    redraw = true;
+                }
+                SelectPlanes(planes) => {
+                    self.planes = planes & 0b11;
                 }
 
                 GetTimer(x) => {
-                    self.set_register(x.loc, self.delay_timer);
+                    self.set_register(x, self.delay_timer);
                 }
                 SetTimer(x) => {
-                    self.delay_timer = x.value;
+                    self.delay_timer = self.get_register(x);
                 }
                 SetSound(x) => {
-                    self.sound_timer = x.value;
+                    self.sound_timer = self.get_register(x);
                 }
 
                 GetDigit(x) => {
-                    self.mem_pointer = (x.value as u16) * 5;
+                    self.mem_pointer = font::SMALL_FONT_ADDR + (self.get_register(x) as u16 & 0xF) * 5;
                 }
                 GetLargeDigit(x) => {
-                    self.mem_pointer = (x.value as u16) * 10 + 80;
+                    self.mem_pointer = font::LARGE_FONT_ADDR + (self.get_register(x) as u16 & 0xF) * 10;
                 }
                 StoreDecimal(x) => {
-                    let x = x.value;
-                    self.set_ram(self.mem_pointer, x / 100);
-                    self.set_ram(self.mem_pointer + 1, (x / 10) % 10);
-                    self.set_ram(self.mem_pointer + 2, x % 10);
+                    let x = self.get_register(x);
+                    self.set_ram(self.mem_pointer, x / 100)?;
+                    self.set_ram(self.pointer_plus(1)?, (x / 10) % 10)?;
+                    self.set_ram(self.pointer_plus(2)?, x % 10)?;
                 }
 
                 StoreRegisters(x) => {
-                    let x = x.loc;
                     for i in 0..=x as u16 {
                         let reg = self.get_register(i as u8);
-                        self.set_ram(self.mem_pointer + i, reg);
+                        self.set_ram(self.pointer_plus(i)?, reg)?;
                     }
+                    self.increment_mem_pointer(x);
                 }
                 LoadRegisters(x) => {
-                    let x = x.loc;
                     for i in 0..=x as u16 {
-                        let val = self.get_ram(self.mem_pointer + i);
+                        let val = self.get_ram(self.pointer_plus(i)?)?;
                         self.set_register(i as u8, val);
                     }
+                    self.increment_mem_pointer(x);
                 }
                 StoreRegistersRPL(x) => {
-                    let x = x.loc;
-                    for i in 0..=x as u8 {
-                        let reg = self.get_register(i as u8);
-                        self.set_rpl(i, reg);
+                    for i in 0..=x {
+                        let reg = self.get_register(i);
+                        self.set_rpl(i, reg)?;
                     }
                 }
                 LoadRegistersRPL(x) => {
-                    let x = x.loc;
-                    for i in 0..=x as u8 {
-                        let val = self.get_rpl(i);
+                    for i in 0..=x {
+                        let val = self.get_rpl(i)?;
                         self.set_register(i, val);
                     }
                 }
-                Invalid => {}
+                SaveRange { x, y } => {
+                    for (i, reg) in helpers::register_range(x, y).enumerate() {
+                        let val = self.get_register(reg);
+                        self.set_ram(self.pointer_plus(i as u16)?, val)?;
+                    }
+                }
+                LoadRange { x, y } => {
+                    for (i, reg) in helpers::register_range(x, y).enumerate() {
+                        let val = self.get_ram(self.pointer_plus(i as u16)?)?;
+                        self.set_register(reg, val);
+                    }
+                }
+                LongPointer => {
+                    let msb = self.get_ram(pc.wrapping_add(2))?;
+                    let lsb = self.get_ram(pc.wrapping_add(3))?;
+                    self.mem_pointer = u16::from_be_bytes([msb, lsb]);
+                    self.pro_counter = pc.wrapping_add(4);
+                }
+                LoadAudio => {
+                    let pattern = self.get_ram_bytes(self.mem_pointer as usize, 16)?;
+                    self.audio_pattern = pattern.try_into().unwrap();
+                    self.pattern_loaded = true;
+                    self.beeper.pattern(self.audio_pattern, self.pitch);
+                }
+                SetPitch(x) => {
+                    self.pitch = self.get_register(x);
+                    // before any F002 the buzzer is still the square wave, the pitch waits for a pattern
+                    if self.pattern_loaded {
+                        self.beeper.pattern(self.audio_pattern, self.pitch);
+                    }
+                }
+                Invalid(opcode) => {
+                    return Err(EmulatorError::InvalidOpcode { pc, opcode });
+                }
+            }
+        }
+
+        Ok(redraw)
+    }
+
+    fn skip(&mut self) {
+        // XO-CHIP skips step over the whole 4-byte F000 NNNN
+        let pc = self.pro_counter as usize;
+        let long = self.platform == Platform::XoChip
+            && self.ram.get(pc) == Some(&0xF0)
+            && self.ram.get(pc + 1) == Some(&0x00);
+        self.pro_counter = self.pro_counter.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    fn draw_waits(&self) -> bool {
+        match self.quirks.display_wait {
+            DisplayWait::Never => false,
+            DisplayWait::Always => true,
+            DisplayWait::LowResolution => matches!(self.resolution_mode, Resolution::Low),
+        }
+    }
+
+    fn set_result_and_flag(&mut self, x: u8, result: u8, flag: u8) {
+        // when x is VF, whichever goes in last is what it keeps
+        if self.quirks.flag_last {
+            self.set_register(x, result);
+            self.set_register(0xF, flag);
+        } else {
+            self.set_register(0xF, flag);
+            self.set_register(x, result);
+        }
+    }
+
+    fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let planes = self.planes;
+        (0..2).filter(move |plane| planes & (1 << plane) != 0)
+    }
+
+    fn draw_sprite<const W: usize>(&mut self, plane: usize, x: u8, y: u8, sprite: &[[bool; W]]) -> bool {
+        // returns whether any pixel was turned off
+        let (width, height) = match self.resolution_mode {
+            Resolution::High => (128, 64),
+            Resolution::Low => (64, 32),
+        };
+        // the start position always wraps, the rest of the sprite by quirk
+        let (x, y) = (x as usize % width, y as usize % height);
+        let mut collision = false;
+        for (y_o, row) in sprite.iter().enumerate() {
+            for (x_o, pixel) in row.iter().enumerate() {
+                if !*pixel {
+                    continue;
+                }
+                let (x_pos, y_pos) = match self.quirks.sprite_edges {
+                    SpriteEdges::Wrap => ((x + x_o) % width, (y + y_o) % height),
+                    SpriteEdges::Clip if x + x_o >= width || y + y_o >= height => continue,
+                    SpriteEdges::Clip => (x + x_o, y + y_o),
+                };
+                collision |= match self.resolution_mode {
+                    Resolution::High => self.draw_hi(plane, x_pos, y_pos),
+                    Resolution::Low => self.draw_lo(plane, x_pos, y_pos),
+                };
+            }
+        }
+        collision
+    }
+
+    fn scroll_distance(&self, pixels: u8) -> usize {
+        // SCHIP scrolls by high resolution pixels in either mode, XO-CHIP by the current ones
+        match (self.platform, &self.resolution_mode) {
+            (Platform::XoChip, Resolution::Low) => pixels as usize * 2,
+            _ => pixels as usize,
+        }
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        // moves the selected planes by (dx, dy), whatever comes in from the edge is unlit
+        for plane in self.selected_planes() {
+            let old = self.display[plane];
+            for (x, column) in self.display[plane].iter_mut().enumerate() {
+                for (y, pixel) in column.iter_mut().enumerate() {
+                    let (from_x, from_y) = (x as isize - dx, y as isize - dy);
+                    *pixel = (0..128).contains(&from_x)
+                        && (0..64).contains(&from_y)
+                        && old[from_x as usize][from_y as usize];
+                }
             }
         }
+    }
+
+    // I + offset for the instructions that go through RAM from I. past the end of RAM is
+    // an error, the same as for sprites, never a wrap to 0 or a pile-up on the last byte
+    fn pointer_plus(&self, offset: u16) -> Result<u16, EmulatorError> {
+        self.mem_pointer.checked_add(offset).ok_or(EmulatorError::MemoryOutOfBounds {
+            addr: self.mem_pointer as usize + offset as usize,
+        })
+    }
 
-        redraw
+    fn increment_mem_pointer(&mut self, x: u8) {
+        // how far FX55/FX65 move I depends on the interpreter
+        let increment = match self.quirks.memory_increment {
+            MemoryIncrement::Unchanged => 0,
+            MemoryIncrement::ByX => x as u16,
+            MemoryIncrement::ByXPlusOne => x as u16 + 1,
+        };
+        self.mem_pointer = self.mem_pointer.wrapping_add(increment);
     }
 }
 
 mod helpers {
     pub fn byte_to_bools(byte: u8) -> [bool; 8] {
         let mut ret = [false; 8];
-        for j in 0..8 {
+        for (j, bit) in ret.iter_mut().enumerate() {
             let i = 7 - j;
             let mask = 1 << i;
-            ret[j] = (byte & mask) >> i == 1;
+            *bit = (byte & mask) >> i == 1;
         }
-        return ret;
+        ret
     }
     pub fn twobyte_twobools(bytea: u8, byteb: u8) -> [bool; 16] {
         // tried to call it 2byte2bools, but it wouldn't let me XD
         let mut ret = [false; 16];
         let num = ((bytea as u16) << 8) | (byteb as u16);
-        for j in 0..16 {
+        for (j, bit) in ret.iter_mut().enumerate() {
             let i = 15 - j;
             let mask = 1 << i;
-            ret[j] = (num & mask) >> i == 1;
+            *bit = (num & mask) >> i == 1;
         }
-        return ret;
+        ret
     }
     pub fn load_sprite(bytes: &[u8]) -> Vec<[bool; 8]> {
         let mut ret = Vec::new();
         for byte in bytes {
             ret.push(byte_to_bools(*byte));
         }
-        return ret;
+        ret
+    }
+    pub fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = u8>> {
+        // 5XY2/5XY3 go from x to y, downwards when x is the larger
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
     }
-    pub fn load_large_sprite(bytes: &[u8]) -> Vec<[bool; 16]> {
+    pub fn load_large_sprite(bytes: &[u8; 32]) -> Vec<[bool; 16]> {
         let mut ret = Vec::new();
         for j in 0..16 {
             let i = j * 2;
             ret.push(twobyte_twobools(bytes[i], bytes[i + 1]));
         }
-        return ret;
+        ret
     }
 }
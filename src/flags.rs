@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// the RPL user flags of every ROM that has set any, kept between runs the way
+// the HP48 kept them through power-off. one line per ROM:
+//
+//     <hash of the ROM, 16 hex digits> <flags, 2 hex digits each>
+
+pub struct FlagStore {
+    path: PathBuf,
+    entries: BTreeMap<u64, Vec<u8>>,
+}
+
+// FNV-1a, stable across builds and platforms unlike std's hasher
+pub fn rom_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl FlagStore {
+    // $XDG_DATA_HOME/schip-emu/rpl-flags, falling back to ~/.local/share
+    pub fn default_path() -> Option<PathBuf> {
+        let data = match env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+        };
+        Some(data.join("schip-emu").join("rpl-flags"))
+    }
+
+    // a file that doesn't exist yet is just an empty store
+    pub fn open(path: &Path) -> io::Result<FlagStore> {
+        let mut ret = FlagStore {
+            path: path.to_path_buf(),
+            entries: BTreeMap::new(),
+        };
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ret),
+            Err(e) => return Err(e),
+        };
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad_line = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected `<rom hash> <flags>`, got {:?}", number + 1, line),
+                )
+            };
+            let (hash, flags) = line.split_once(' ').ok_or_else(bad_line)?;
+            let hash = u64::from_str_radix(hash, 16).map_err(|_| bad_line())?;
+            let flags = flags.trim();
+            if flags.len() % 2 != 0 {
+                return Err(bad_line());
+            }
+            let flags = (0..flags.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&flags[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| bad_line())?;
+            ret.entries.insert(hash, flags);
+        }
+        Ok(ret)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, rom_hash: u64) -> Option<&[u8]> {
+        self.entries.get(&rom_hash).map(|flags| flags.as_slice())
+    }
+
+    pub fn set(&mut self, rom_hash: u64, flags: &[u8]) {
+        // ROMs that never touched their flags don't get a line
+        if flags.iter().all(|flag| *flag == 0) && !self.entries.contains_key(&rom_hash) {
+            return;
+        }
+        self.entries.insert(rom_hash, flags.to_vec());
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = String::new();
+        for (hash, flags) in &self.entries {
+            let _ = write!(out, "{:016x} ", hash);
+            for flag in flags {
+                let _ = write!(out, "{:02x}", flag);
+            }
+            out.push('\n');
+        }
+        fs::write(&self.path, out)
+    }
+}
@@ -1,220 +1,325 @@
-use crate::components::Register;
 use crate::Emulator;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Value {
     // to encompass values that are either register or byte
-    Register(Register),
+    Register(u8),
     Byte(u8),
 }
 impl Value {
-    pub fn from_reg(val: Register) -> Value {
-        Value::Register(val)
+    pub fn from_reg(loc: u8) -> Value {
+        Value::Register(loc)
     }
     pub fn from_byte(val: u8) -> Value {
         Value::Byte(val)
     }
-    pub fn unwrap(&self) -> u8 {
+    pub fn resolve(&self, emulator: &Emulator) -> u8 {
         match self {
             Value::Byte(byte) => *byte,
-            Value::Register(reg) => reg.value,
+            Value::Register(loc) => emulator.get_register(*loc),
         }
     }
 }
 
-#[derive(Debug)]
+// registers are carried as indices (0x0-0xF) and read when the instruction runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     ClearScreen, // clear the screen
 
     Jump(u16), // jump to addr
     JumpPlus {
         addr: u16,
-        x: Register,
-    }, // jump to addr + R{X}
+        x: u8,
+    }, // jump to addr + R{X} (or addr + R{0}, by quirk)
 
     Call(u16), // call a procedure at addr
     Return,    // return from a procedure
 
     SkipIfEqual {
-        reg: Register,
+        reg: u8,
         comp: Value,
     }, // skip if a == b
     SkipIfUnequal {
-        reg: Register,
+        reg: u8,
         comp: Value,
     }, // skip if a != b
 
-    SkipIfKey(Register),    // skip if the key with the value of reg is pressed
-    SkipIfNotKey(Register), // skip if the key with the value of reg is not pressed
-    KeyBlock(Register),     // block for keypress, store value in register
+    SkipIfKey(u8),    // skip if the key with the value of reg is pressed
+    SkipIfNotKey(u8), // skip if the key with the value of reg is not pressed
+    KeyBlock(u8),     // block for keypress, store value in register
 
     Load {
-        reg: Register,
+        reg: u8,
         value: Value,
     }, // load value into register
 
     AddInPlace {
-        reg: Register,
+        reg: u8,
         byte: u8,
     }, // add byte to register, store result in register
 
     Or {
-        x: Register,
-        y: Register,
+        x: u8,
+        y: u8,
     }, // OR x and y, store in x
     And {
-        x: Register,
-        y: Register,
+        x: u8,
+        y: u8,
     }, // AND x and y, store in x
     Xor {
-        x: Register,
-        y: Register,
+        x: u8,
+        y: u8,
     }, // XOR x and y, store in x
     Add {
-        x: Register,
-        y: Register,
+        x: u8,
+        y: u8,
     }, // ADD x and y, store in x (sets overflow flag)
     Sub {
-        x: Register,
-        y: Register,
+        x: u8,
+        y: u8,
     }, // SUB x and y, store in x (sets !overflow flag)
-    ShiftRight(Register), // SHR x in-place (sets overflow flag)
-    ShiftLeft(Register),  // SHL x in-place (sets overflow flag)
+    SubReverse {
+        x: u8,
+        y: u8,
+    }, // SUB y and x, store in x (sets !overflow flag)
+    ShiftRight {
+        x: u8,
+        y: u8,
+    }, // SHR x (or y, by quirk) into x (sets overflow flag)
+    ShiftLeft {
+        x: u8,
+        y: u8,
+    }, // SHL x (or y, by quirk) into x (sets overflow flag)
 
     SetPointer(u16),      // set the memory pointer to addr
-    AddPointer(Register), // adds register to memory pointer
+    AddPointer(u8), // adds register to memory pointer
 
     Random {
-        x: Register,
+        x: u8,
         byte: u8,
     }, // random value & byte, goes in x
 
     HighResolution(bool), // changes the resolution mode
     Draw {
-        x: Register,
-        y: Register,
+        x: u8,
+        y: u8,
         byte_count: u8,
     }, // draw byte_count bytes at (x,y)
     DrawLarge {
-        x: Register,
-        y: Register,
+        x: u8,
+        y: u8,
     }, // draws a 16x16 sprite at (x,y) (only in high-res mode)
     ScrollRight,
     ScrollLeft,
     ScrollDown(u8),
+    ScrollUp(u8),       // XO-CHIP
+    SelectPlanes(u8),   // XO-CHIP, bitmask of the planes drawn to, cleared and scrolled
 
-    GetTimer(Register), // set reg to delay timer
-    SetTimer(Register), // set delay timer to reg
-    SetSound(Register), // set sound timer to reg
+    GetTimer(u8), // set reg to delay timer
+    SetTimer(u8), // set delay timer to reg
+    SetSound(u8), // set sound timer to reg
 
-    GetDigit(Register), // sets I to the location of the character representing reg
-    GetLargeDigit(Register), // sets I to the location of the large character (0-9)
-    StoreDecimal(Register), // stores the decimal representation of reg in RAM
+    GetDigit(u8), // sets I to the location of the character representing reg
+    GetLargeDigit(u8), // sets I to the location of the large character (0-F)
+    StoreDecimal(u8), // stores the decimal representation of reg in RAM
 
-    StoreRegisters(Register),    // stores registers 0..reg in RAM
-    LoadRegisters(Register),     // loads registers 0..reg from RAM
-    StoreRegistersRPL(Register), // stores registers 0..reg in RPL memory
-    LoadRegistersRPL(Register),  // loads registers 0..reg from RPL memory
+    StoreRegisters(u8),    // stores registers 0..reg in RAM
+    LoadRegisters(u8),     // loads registers 0..reg from RAM
+    StoreRegistersRPL(u8), // stores registers 0..reg in RPL memory
+    LoadRegistersRPL(u8),  // loads registers 0..reg from RPL memory
 
-    Invalid, // this is passed if the instruction didn't exist
+    SaveRange {
+        x: u8,
+        y: u8,
+    }, // XO-CHIP, stores registers x..y in RAM (backwards if x > y), I is unchanged
+    LoadRange {
+        x: u8,
+        y: u8,
+    }, // XO-CHIP, loads registers x..y from RAM the same way
+    LongPointer, // XO-CHIP, sets I to the 16-bit address in the next word
+    LoadAudio,   // XO-CHIP, loads the 16-byte audio pattern from I
+    SetPitch(u8), // XO-CHIP, sets the audio pattern playback pitch to reg
+
+    Invalid(u16), // this is passed if the instruction didn't exist, keeps the opcode
 }
 impl Instruction {
-    pub fn from(emulator: &Emulator, msb: u8, lsb: u8) -> Instruction {
-        let opcode = (msb & 0xF0) >> 4;
+    pub fn decode(opcode: u16) -> Instruction {
+        let [msb, lsb] = opcode.to_be_bytes();
         let x = msb & 0x0F;
         let y = (lsb & 0xF0) >> 4;
-        let x_reg = emulator.get_register_data(x);
-        let y_reg = emulator.get_register_data(y);
         let byte = lsb;
         let nibble = lsb & 0x0F;
-        let addr = (((msb as u16) << 8) | (lsb as u16)) & 0x0FFF;
+        let addr = opcode & 0x0FFF;
         use Instruction::*;
-        match opcode {
-            0x0 => match byte {
-                0xE0 => ClearScreen,
-                0xEE => Return,
-                0xFB => ScrollRight,
-                0xFC => ScrollLeft,
-                0xFE => HighResolution(false),
-                0xFF => HighResolution(true),
-                _ => {
-                    if y == 0xC {
-                        ScrollDown(nibble)
-                    } else {
-                        Invalid
-                    }
-                }
+        match msb >> 4 {
+            0x0 => match addr {
+                0x0E0 => ClearScreen,
+                0x0EE => Return,
+                0x0FB => ScrollRight,
+                0x0FC => ScrollLeft,
+                0x0FE => HighResolution(false),
+                0x0FF => HighResolution(true),
+                0x0C0..=0x0CF => ScrollDown(nibble),
+                0x0D0..=0x0DF => ScrollUp(nibble),
+                _ => Invalid(opcode),
             },
             0x1 => Jump(addr),
             0x2 => Call(addr),
             0x3 => SkipIfEqual {
-                reg: x_reg,
+                reg: x,
                 comp: Value::from_byte(byte),
             },
             0x4 => SkipIfUnequal {
-                reg: x_reg,
+                reg: x,
                 comp: Value::from_byte(byte),
             },
-            0x5 => SkipIfEqual {
-                reg: x_reg,
-                comp: Value::from_reg(y_reg),
+            0x5 => match nibble {
+                0x0 => SkipIfEqual {
+                    reg: x,
+                    comp: Value::from_reg(y),
+                },
+                0x2 => SaveRange { x, y },
+                0x3 => LoadRange { x, y },
+                _ => Invalid(opcode),
             },
             0x6 => Load {
-                reg: x_reg,
+                reg: x,
                 value: Value::from_byte(byte),
             },
-            0x7 => AddInPlace { reg: x_reg, byte },
+            0x7 => AddInPlace { reg: x, byte },
             0x8 => match nibble {
                 0x0 => Load {
-                    reg: x_reg,
-                    value: Value::from_reg(y_reg),
+                    reg: x,
+                    value: Value::from_reg(y),
                 },
-                0x1 => Or { x: x_reg, y: y_reg },
-                0x2 => And { x: x_reg, y: y_reg },
-                0x3 => Xor { x: x_reg, y: y_reg },
-                0x4 => Add { x: x_reg, y: y_reg },
-                0x5 => Sub { x: x_reg, y: y_reg },
-                0x6 => ShiftRight(x_reg),
-                0x7 => Sub { x: y_reg, y: x_reg },
-                0xE => ShiftLeft(x_reg),
-                _ => Invalid,
+                0x1 => Or { x, y },
+                0x2 => And { x, y },
+                0x3 => Xor { x, y },
+                0x4 => Add { x, y },
+                0x5 => Sub { x, y },
+                0x6 => ShiftRight { x, y },
+                0x7 => SubReverse { x, y },
+                0xE => ShiftLeft { x, y },
+                _ => Invalid(opcode),
             },
-            0x9 => SkipIfUnequal {
-                reg: x_reg,
-                comp: Value::from_reg(y_reg),
+            0x9 if nibble == 0 => SkipIfUnequal {
+                reg: x,
+                comp: Value::from_reg(y),
             },
             0xA => SetPointer(addr),
-            0xB => JumpPlus { addr, x: x_reg },
-            0xC => Random { x: x_reg, byte },
+            0xB => JumpPlus { addr, x },
+            0xC => Random { x, byte },
             0xD => match nibble {
-                0x0 => DrawLarge { x: x_reg, y: y_reg },
+                0x0 => DrawLarge { x, y },
                 _ => Draw {
-                    x: x_reg,
-                    y: y_reg,
+                    x,
+                    y,
                     byte_count: nibble,
                 },
             },
             0xE => match byte {
-                0x9E => SkipIfKey(x_reg),
-                0xA1 => SkipIfNotKey(x_reg),
-                _ => Invalid,
+                0x9E => SkipIfKey(x),
+                0xA1 => SkipIfNotKey(x),
+                _ => Invalid(opcode),
             },
             0xF => match byte {
-                0x07 => GetTimer(x_reg),
-                0x0A => KeyBlock(x_reg),
-                0x15 => SetTimer(x_reg),
-                0x18 => SetSound(x_reg),
-                0x1E => AddPointer(x_reg),
-                0x29 => GetDigit(x_reg),
-                0x30 => GetLargeDigit(x_reg),
-                0x33 => StoreDecimal(x_reg),
-                0x55 => StoreRegisters(x_reg),
-                0x65 => LoadRegisters(x_reg),
-                0x75 => StoreRegistersRPL(x_reg),
-                0x85 => LoadRegistersRPL(x_reg),
-                _ => Invalid,
+                0x00 if x == 0 => LongPointer,
+                0x01 => SelectPlanes(x),
+                0x02 if x == 0 => LoadAudio,
+                0x07 => GetTimer(x),
+                0x0A => KeyBlock(x),
+                0x15 => SetTimer(x),
+                0x18 => SetSound(x),
+                0x1E => AddPointer(x),
+                0x29 => GetDigit(x),
+                0x30 => GetLargeDigit(x),
+                0x33 => StoreDecimal(x),
+                0x3A => SetPitch(x),
+                0x55 => StoreRegisters(x),
+                0x65 => LoadRegisters(x),
+                0x75 => StoreRegistersRPL(x),
+                0x85 => LoadRegistersRPL(x),
+                _ => Invalid(opcode),
             },
-            _ => Invalid,
+            _ => Invalid(opcode),
         }
     }
+
+    // the inverse of decode, register indices and immediates are masked to fit
+    pub fn encode(&self) -> u16 {
+        let x = |x: u8| (x as u16 & 0xF) << 8;
+        let xy = |x: u8, y: u8| (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4;
+        let xb = |x: u8, byte: u8| (x as u16 & 0xF) << 8 | byte as u16;
+        use Instruction::*;
+        match *self {
+            ClearScreen => 0x00E0,
+            Return => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            HighResolution(false) => 0x00FE,
+            HighResolution(true) => 0x00FF,
+            ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            SelectPlanes(n) => 0xF001 | x(n),
+            Jump(addr) => 0x1000 | (addr & 0xFFF),
+            Call(addr) => 0x2000 | (addr & 0xFFF),
+            SkipIfEqual { reg, comp: Value::Byte(byte) } => 0x3000 | xb(reg, byte),
+            SkipIfUnequal { reg, comp: Value::Byte(byte) } => 0x4000 | xb(reg, byte),
+            SkipIfEqual { reg, comp: Value::Register(y) } => 0x5000 | xy(reg, y),
+            Load { reg, value: Value::Byte(byte) } => 0x6000 | xb(reg, byte),
+            AddInPlace { reg, byte } => 0x7000 | xb(reg, byte),
+            Load { reg, value: Value::Register(y) } => 0x8000 | xy(reg, y),
+            Or { x, y } => 0x8001 | xy(x, y),
+            And { x, y } => 0x8002 | xy(x, y),
+            Xor { x, y } => 0x8003 | xy(x, y),
+            Add { x, y } => 0x8004 | xy(x, y),
+            Sub { x, y } => 0x8005 | xy(x, y),
+            ShiftRight { x, y } => 0x8006 | xy(x, y),
+            SubReverse { x, y } => 0x8007 | xy(x, y),
+            ShiftLeft { x, y } => 0x800E | xy(x, y),
+            SkipIfUnequal { reg, comp: Value::Register(y) } => 0x9000 | xy(reg, y),
+            SetPointer(addr) => 0xA000 | (addr & 0xFFF),
+            JumpPlus { addr, x } => 0xB000 | (x as u16 & 0xF) << 8 | (addr & 0xFF),
+            Random { x, byte } => 0xC000 | xb(x, byte),
+            Draw { x, y, byte_count } => 0xD000 | xy(x, y) | (byte_count as u16 & 0xF),
+            DrawLarge { x, y } => 0xD000 | xy(x, y),
+            SkipIfKey(reg) => 0xE09E | x(reg),
+            SkipIfNotKey(reg) => 0xE0A1 | x(reg),
+            GetTimer(reg) => 0xF007 | x(reg),
+            KeyBlock(reg) => 0xF00A | x(reg),
+            SetTimer(reg) => 0xF015 | x(reg),
+            SetSound(reg) => 0xF018 | x(reg),
+            AddPointer(reg) => 0xF01E | x(reg),
+            GetDigit(reg) => 0xF029 | x(reg),
+            GetLargeDigit(reg) => 0xF030 | x(reg),
+            StoreDecimal(reg) => 0xF033 | x(reg),
+            StoreRegisters(reg) => 0xF055 | x(reg),
+            LoadRegisters(reg) => 0xF065 | x(reg),
+            StoreRegistersRPL(reg) => 0xF075 | x(reg),
+            LoadRegistersRPL(reg) => 0xF085 | x(reg),
+            SaveRange { x, y } => 0x5002 | xy(x, y),
+            LoadRange { x, y } => 0x5003 | xy(x, y),
+            LongPointer => 0xF000,
+            LoadAudio => 0xF002,
+            SetPitch(reg) => 0xF03A | x(reg),
+            Invalid(opcode) => opcode,
+        }
+    }
+
+    // bytes taken up in RAM, LongPointer carries its address in a second word
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LongPointer => 4,
+            _ => 2,
+        }
+    }
+
+    // the instructions only XO-CHIP has, SCHIP treats them as invalid
+    pub fn is_xo_chip(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            ScrollUp(_) | SelectPlanes(_) | SaveRange { .. } | LoadRange { .. } | LongPointer | LoadAudio | SetPitch(_)
+        )
+    }
 }
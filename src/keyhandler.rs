@@ -1,47 +1,66 @@
-
-use olc_pge::Key;
-
-const KEYS: [Key;0x10] = [
-    Key::X,  // 0
-    Key::K1, // 1
-    Key::K2, // 2
-    Key::K3, // 3
-    Key::Q,  // 4
-    Key::W,  // 5
-    Key::E,  // 6
-    Key::A,  // 7
-    Key::S,  // 8
-    Key::D,  // 9
-    Key::Z,  // A
-    Key::C,  // B
-    Key::K4, // C
-    Key::R,  // D
-    Key::F,  // E
-    Key::V,  // F
-];
+// where an FX0A is in waiting for its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyWait {
+    Idle,
+    Press(u8),                    // waiting for any key to go down, to store in the register
+    Release { reg: u8, key: u8 }, // key went down, waiting for it to come back up
+}
 
 pub struct KeyHandler {
     pub keys: [bool;0x10],
-    pub key_hold: u8,
+    pub last_keys: [bool;0x10],
+    pub wait: KeyWait,
 }
 impl KeyHandler {
     pub fn new () -> Self {
         Self {
             keys: [false;0x10],
-            key_hold: 0x10,
+            last_keys: [false;0x10],
+            wait: KeyWait::Idle,
         }
     }
-    pub fn update_keys (&mut self, pge: &olc_pge::PixelGameEngine) {
-        for (i, key) in KEYS.iter().enumerate() {
-            self.keys[i] = pge.get_key(*key).held;
-        }
+    pub fn update_keys (&mut self, keys: [bool;0x10]) {
+        self.last_keys = self.keys;
+        self.keys = keys;
+    }
+    pub fn key_block_pressed (&mut self) -> Option<u8> {
+        // a key counts as pressed on the update it goes down
+        (0..0x10)
+            .find(|&i| self.keys[i] && !self.last_keys[i])
+            .map(|i| i as u8)
+    }
+    pub fn start_wait (&mut self, reg: u8) {
+        self.wait = KeyWait::Press(reg);
     }
-    pub fn key_block_pressed (&mut self, pge: &olc_pge::PixelGameEngine) -> Option<u8> {
-        for (i, key) in KEYS.iter().enumerate() {
-            if pge.get_key(*key).pressed {
-                return Some(i as u8)
+    pub fn is_waiting (&self) -> bool {
+        self.wait != KeyWait::Idle
+    }
+    // moves the wait along after the keys change, returning the register and key once it's over.
+    // on_release holds on until the key is let go, like the COSMAC VIP did
+    pub fn finish_wait (&mut self, on_release: bool) -> Option<(u8, u8)> {
+        match self.wait {
+            KeyWait::Idle => None,
+            KeyWait::Press(reg) => {
+                let key = self.key_block_pressed()?;
+                if on_release {
+                    self.wait = KeyWait::Release { reg, key };
+                    return None;
+                }
+                self.wait = KeyWait::Idle;
+                Some((reg, key))
+            }
+            KeyWait::Release { reg, key } => {
+                if self.keys[key as usize] {
+                    return None;
+                }
+                self.wait = KeyWait::Idle;
+                Some((reg, key))
             }
         }
-        return None
     }
-}
\ No newline at end of file
+}
+impl Default for KeyHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// which host keys press which of the 16 CHIP-8 keys. host keys are named in
+// lowercase (`x`, `1`, `up`, `np8`, ...) and the frontend decides what a name means.
+// the file has a section for every ROM, after the lines that apply to all of them:
+//
+//     layout = azerty
+//     5 = z up
+//     [<hash of the ROM, 16 hex digits>]
+//     layout = numpad
+//     a = np0 space
+//
+// `layout` starts over from a preset, and `<key> = <host keys...>` rebinds one key
+// (to nothing, if no host keys follow)
+
+// the arrangements of the COSMAC VIP keypad on a host keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    #[default]
+    Qwerty, // the 4x4 block from 1 to V
+    Azerty, // the same block on a French keyboard, from 1 to V
+    Numpad, // digits on the same numpad digits, A-F on the keys around them
+}
+impl Layout {
+    pub const NAMES: [&'static str; 3] = ["qwerty", "azerty", "numpad"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Qwerty => "qwerty",
+            Layout::Azerty => "azerty",
+            Layout::Numpad => "numpad",
+        }
+    }
+
+    // host keys for 0 to F
+    fn keys(&self) -> [&'static str; 0x10] {
+        match self {
+            Layout::Qwerty => [
+                "x", "1", "2", "3", "q", "w", "e", "a", "s", "d", "z", "c", "4", "r", "f", "v",
+            ],
+            Layout::Azerty => [
+                "x", "1", "2", "3", "a", "z", "e", "q", "s", "d", "w", "c", "4", "r", "f", "v",
+            ],
+            Layout::Numpad => [
+                "np0", "np1", "np2", "np3", "np4", "np5", "np6", "np7", "np8", "np9", "np/", "np*", "np-",
+                "np+", "npenter", "np.",
+            ],
+        }
+    }
+}
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+impl FromStr for Layout {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "qwerty" => Ok(Layout::Qwerty),
+            "azerty" => Ok(Layout::Azerty),
+            "numpad" => Ok(Layout::Numpad),
+            _ => Err(format!(
+                "unknown key layout {:?} (expected one of: {})",
+                s,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    keys: [Vec<String>; 0x10], // host keys bound to each CHIP-8 key, any of them presses it
+}
+impl KeyMap {
+    pub fn from_layout(layout: Layout) -> KeyMap {
+        KeyMap {
+            keys: layout.keys().map(|host| vec![host.to_string()]),
+        }
+    }
+
+    pub fn get(&self, key: u8) -> &[String] {
+        &self.keys[key as usize & 0xF]
+    }
+    // replaces what key is bound to, a host key bound elsewhere moves here
+    pub fn set(&mut self, key: u8, hosts: &[String]) {
+        for bound in self.keys.iter_mut() {
+            bound.retain(|host| !hosts.contains(host));
+        }
+        self.keys[key as usize & 0xF] = hosts.to_vec();
+    }
+
+    fn apply(&mut self, section: &Section) {
+        if let Some(layout) = section.layout {
+            *self = KeyMap::from_layout(layout);
+        }
+        for (key, hosts) in &section.keys {
+            self.set(*key, hosts);
+        }
+    }
+}
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::from_layout(Layout::default())
+    }
+}
+
+// the lines of one part of the file, in the order they apply
+#[derive(Default)]
+struct Section {
+    layout: Option<Layout>,
+    keys: Vec<(u8, Vec<String>)>, // a host key named twice ends up on the later key
+}
+impl Section {
+    fn write(&self, out: &mut String) {
+        if let Some(layout) = self.layout {
+            let _ = writeln!(out, "layout = {}", layout);
+        }
+        for (key, hosts) in &self.keys {
+            let line = format!("{:x} = {}", key, hosts.join(" "));
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+    }
+}
+
+pub struct KeyMapFile {
+    path: PathBuf,
+    all: Section,
+    roms: BTreeMap<u64, Section>,
+}
+
+impl KeyMapFile {
+    // $XDG_CONFIG_HOME/schip-emu/keymap, falling back to ~/.config
+    pub fn default_path() -> Option<PathBuf> {
+        let config = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(config.join("schip-emu").join("keymap"))
+    }
+
+    // a file that doesn't exist yet maps everything to QWERTY
+    pub fn open(path: &Path) -> io::Result<KeyMapFile> {
+        let mut ret = KeyMapFile {
+            path: path.to_path_buf(),
+            all: Section::default(),
+            roms: BTreeMap::new(),
+        };
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ret),
+            Err(e) => return Err(e),
+        };
+        let mut rom = None;
+        for (number, line) in source.lines().enumerate() {
+            let line = match line.split_once('#') {
+                Some((before, _)) => before.trim(),
+                None => line.trim(),
+            };
+            if line.is_empty() {
+                continue;
+            }
+            let bad_line = |message: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}, got {:?}", number + 1, message, line),
+                )
+            };
+            if let Some(hash) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                let hash = u64::from_str_radix(hash.trim(), 16)
+                    .map_err(|_| bad_line("expected `[<rom hash>]`"))?;
+                ret.roms.entry(hash).or_default();
+                rom = Some(hash);
+                continue;
+            }
+            let section = match rom {
+                Some(hash) => ret.roms.entry(hash).or_default(),
+                None => &mut ret.all,
+            };
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| bad_line("expected `<key> = <host keys>` or `layout = <layout>`"))?;
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("layout") {
+                let layout = value.parse().map_err(|e: String| bad_line(&e))?;
+                // a layout replaces everything above it
+                section.layout = Some(layout);
+                section.keys.clear();
+                continue;
+            }
+            let key = match u8::from_str_radix(name, 16) {
+                Ok(key) if key < 0x10 => key,
+                _ => return Err(bad_line("keys go from 0 to f")),
+            };
+            // no host keys at all leaves the key unbound
+            let hosts: Vec<String> = value.split_whitespace().map(str::to_ascii_lowercase).collect();
+            section.keys.retain(|(bound, _)| *bound != key);
+            section.keys.push((key, hosts));
+        }
+        Ok(ret)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // the lines for every ROM, then the ROM's own, over a layout
+    pub fn get(&self, rom_hash: u64, layout: Layout) -> KeyMap {
+        let mut ret = KeyMap::from_layout(layout);
+        ret.apply(&self.all);
+        if let Some(section) = self.roms.get(&rom_hash) {
+            ret.apply(section);
+        }
+        ret
+    }
+
+    // gives the ROM all 16 keys of its own
+    pub fn set(&mut self, rom_hash: u64, map: &KeyMap) {
+        let section = Section {
+            layout: None,
+            keys: (0..0x10).map(|key| (key, map.get(key).to_vec())).collect(),
+        };
+        self.roms.insert(rom_hash, section);
+    }
+
+    // comments in the file aren't kept
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = String::new();
+        self.all.write(&mut out);
+        for (hash, section) in &self.roms {
+            let _ = writeln!(out, "[{:016x}]", hash);
+            section.write(&mut out);
+        }
+        fs::write(&self.path, out)
+    }
+}
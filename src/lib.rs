@@ -0,0 +1,217 @@
+pub mod assembler;
+pub mod audio;
+pub mod components;
+pub mod debugger;
+pub mod disasm;
+mod dump;
+pub mod error;
+mod execution;
+pub mod flags;
+pub mod font;
+pub mod instruction;
+pub mod keyhandler;
+pub mod keymap;
+pub mod movie;
+pub mod quirks;
+pub mod random;
+mod rewind;
+pub mod savestate;
+pub mod scheduler;
+use audio::{Beeper, NullBeeper};
+use components::{RamWrite, Register, Resolution};
+pub use error::EmulatorError;
+use keyhandler::KeyHandler;
+use quirks::{Platform, Quirks};
+use random::{RandomSource, Seeded};
+use rewind::{Input, Rewind};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+// parses a RAM address given on the command line, in hex (0x200) or decimal (512)
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    };
+    // any 16-bit address, XO-CHIP has 64K and the RAM accessors catch the rest
+    parsed.map_err(|e| e.to_string())
+}
+
+pub struct EmulatorConfig {
+    pub rom: PathBuf,
+    pub load_address: u16,
+    pub font: Option<PathBuf>, // replaces the built-in font
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub rpl_flags: Option<u8>, // 8 or 16 RPL flags, None for the platform's
+}
+
+pub struct Emulator {
+    registers: Vec<Register>,
+
+    resolution_mode: Resolution,
+    display: [[[bool; 64]; 128]; 2], // one buffer per bitplane, SCHIP only uses the first
+    planes: u8,                      // bitmask of the planes drawing goes to
+
+    ram: Vec<u8>, // 4K, or 64K on XO-CHIP
+    rpl: [u8; 16],
+    rpl_count: u8, // how many of the RPL flags FX75/FX85 can reach
+    rom_hash: u64, // identifies the ROM's flags between runs
+
+    key_handler: KeyHandler,
+
+    call_stack: Vec<u16>,
+
+    pro_counter: u16,
+    mem_pointer: u16,
+
+    delay_timer: u8,
+    sound_timer: u8,
+    vblank_wait: bool,       // a draw is holding the CPU until the next tick
+    beeper: Box<dyn Beeper>, // sounds while sound_timer is running
+    audio_pattern: [u8; 16], // XO-CHIP sample buffer
+    pattern_loaded: bool,    // F002 has run, the square wave is gone until power-off
+    pitch: u8,
+
+    platform: Platform,
+    quirks: Quirks,
+
+    rng: Box<dyn RandomSource>, // CXNN's numbers
+
+    ram_watches: BTreeSet<u16>,
+    ram_watch_hits: Vec<RamWrite>,
+
+    rewind: Option<Rewind>, // history for stepping backward, off unless asked for
+}
+
+impl Emulator {
+    pub fn with_config(config: EmulatorConfig) -> Result<Emulator, EmulatorError> {
+        let mut ret = Emulator::new(config.platform, config.quirks);
+        ret.pro_counter = config.load_address;
+        if let Some(font) = &config.font {
+            ret.load_rom(font::SMALL_FONT_ADDR, font)?;
+        }
+        let addr = config.load_address as usize;
+        let size = ret.load_rom(config.load_address, &config.rom)?;
+        ret.rom_hash = flags::rom_hash(&ret.ram[addr..addr + size]);
+        if let Some(count) = config.rpl_flags {
+            ret.rpl_count = count.min(16);
+        }
+        Ok(ret)
+    }
+
+    // a machine with nothing but the font in RAM, starting at 0x200
+    pub fn new(platform: Platform, quirks: Quirks) -> Emulator {
+        let mut registers = Vec::new();
+        for i in 0..0x10 {
+            registers.push(components::Register::new(i));
+        }
+
+        let mut ret = Self {
+            registers,
+            resolution_mode: Resolution::Low,
+            display: [[[false; 64]; 128]; 2],
+            planes: 0b01,
+            ram: vec![0u8; platform.ram_size()],
+            rpl: [0u8; 16],
+            rpl_count: platform.rpl_flags(),
+            rom_hash: 0,
+            key_handler: KeyHandler::new(),
+            call_stack: Vec::new(),
+            pro_counter: 0x200,
+            mem_pointer: 0x000,
+            delay_timer: 0x00,
+            sound_timer: 0x00,
+            vblank_wait: false,
+            beeper: Box::new(NullBeeper),
+            audio_pattern: [0u8; 16],
+            pattern_loaded: false,
+            pitch: 64,
+            platform,
+            quirks,
+            rng: Box::new(Seeded::from_entropy()),
+            ram_watches: BTreeSet::new(),
+            ram_watch_hits: Vec::new(),
+            rewind: None,
+        };
+        ret.load_font();
+        ret
+    }
+
+    fn load_font(&mut self) {
+        let small = font::SMALL_FONT_ADDR as usize;
+        let large = font::LARGE_FONT_ADDR as usize;
+        self.ram[small..small + font::SMALL_FONT.len()].copy_from_slice(&font::SMALL_FONT);
+        self.ram[large..large + font::LARGE_FONT.len()].copy_from_slice(&font::LARGE_FONT);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    // the same source from the same place rolls the same numbers, which is what makes a movie replay
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+    // where the source is now, in the form random::parse_source reads
+    pub fn rng_description(&self) -> String {
+        self.rng.describe()
+    }
+
+    // the first bitplane, all there is to the SCHIP display
+    pub fn display(&self) -> &[[bool; 64]; 128] {
+        &self.display[0]
+    }
+    // the color of a pixel, one bit per plane
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        u8::from(self.display[0][x][y]) | u8::from(self.display[1][x][y]) << 1
+    }
+
+    pub fn set_beeper(&mut self, beeper: Box<dyn Beeper>) {
+        self.beeper = beeper;
+    }
+    pub fn finish_beeper(&mut self) -> std::io::Result<()> {
+        self.beeper.finish()
+    }
+
+    // called once per 60Hz tick
+    pub fn tick_timers(&mut self) {
+        self.record_input(Input::Tick);
+        self.beeper.tick(self.sound_timer > 0);
+        self.advance_timers();
+    }
+    // a tick without the sound, for replaying one
+    fn advance_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.vblank_wait = false;
+    }
+
+    // the frontend hands over the held state of all 16 keys every frame
+    pub fn update_keys(&mut self, keys: [bool; 0x10]) {
+        // held keys that were already held last update change nothing, and logging them
+        // anyway fills the rewind budget with a paused debugger's frames
+        let unchanged = keys == self.key_handler.keys && keys == self.key_handler.last_keys;
+        if !unchanged {
+            self.record_input(Input::Keys(savestate::keys_to_bits(&keys)));
+        }
+        self.key_handler.update_keys(keys);
+        if let Some((reg, key)) = self.key_handler.finish_wait(self.quirks.key_release) {
+            self.set_register(reg, key);
+        }
+    }
+    pub fn waiting_for_key(&self) -> bool {
+        self.key_handler.is_waiting()
+    }
+    // with the display wait quirk, nothing more runs this tick after a draw
+    pub fn waiting_for_vblank(&self) -> bool {
+        self.vblank_wait
+    }
+}
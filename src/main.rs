@@ -1,126 +1,762 @@
-mod components;
-mod execution;
-mod instruction;
-mod keyhandler;
-use components::{Register, Resolution};
-use instruction::{Instruction, Value};
-use keyhandler::KeyHandler;
+use schip_emu::assembler;
+#[cfg(feature = "audio")]
+use schip_emu::audio::LiveBeeper;
+use schip_emu::audio::Tone;
+use schip_emu::debugger::Debugger;
+use schip_emu::flags::FlagStore;
+use schip_emu::keymap::{KeyMap, KeyMapFile, Layout};
+use schip_emu::movie::Movie;
+use schip_emu::disasm::{self, Syntax};
+use schip_emu::quirks::{Platform, QuirkPreset, Quirks};
+use schip_emu::random;
+use schip_emu::scheduler::Scheduler;
+use schip_emu::{parse_address, Emulator, EmulatorConfig, EmulatorError};
 use olc_pge as olc;
-use olc_pge::PixelGameEngine;
-use clap::Parser;
+use olc_pge::{Key, PixelGameEngine};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
 const OFF_COLOR: olc::Pixel = olc::VERY_DARK_GREEN;
 const ON_COLOR: olc::Pixel = olc::DARK_GREEN;
-const FRAME_TIME: f32 = 1.0/1200.0;
+const ERROR_COLOR: olc::Pixel = olc::RED;
+// XO-CHIP pixels lit on the second plane only, and on both
+const PLANE2_COLOR: olc::Pixel = olc::DARK_YELLOW;
+const BOTH_PLANES_COLOR: olc::Pixel = olc::YELLOW;
 
-pub struct Emulator {
-    registers: Vec<Register>,
+// the host keys a key map can name. the F keys, backspace, = and - are the
+// frontend's own and can't be bound
+const HOST_KEYS: [(&str, Key); 78] = [
+    ("a", Key::A), ("b", Key::B), ("c", Key::C), ("d", Key::D), ("e", Key::E), ("f", Key::F),
+    ("g", Key::G), ("h", Key::H), ("i", Key::I), ("j", Key::J), ("k", Key::K), ("l", Key::L),
+    ("m", Key::M), ("n", Key::N), ("o", Key::O), ("p", Key::P), ("q", Key::Q), ("r", Key::R),
+    ("s", Key::S), ("t", Key::T), ("u", Key::U), ("v", Key::V), ("w", Key::W), ("x", Key::X),
+    ("y", Key::Y), ("z", Key::Z),
+    ("0", Key::K0), ("1", Key::K1), ("2", Key::K2), ("3", Key::K3), ("4", Key::K4),
+    ("5", Key::K5), ("6", Key::K6), ("7", Key::K7), ("8", Key::K8), ("9", Key::K9),
+    ("up", Key::Up), ("down", Key::Down), ("left", Key::Left), ("right", Key::Right),
+    ("space", Key::Space), ("tab", Key::Tab), ("enter", Key::Return),
+    ("comma", Key::Comma), ("period", Key::Period), ("apostrophe", Key::Apostrophe),
+    ("backquote", Key::BackQuote), ("semicolon", Key::Semicolon),
+    ("lbracket", Key::LeftBracket), ("rbracket", Key::RightBracket),
+    ("slash", Key::Slash), ("backslash", Key::BackSlash),
+    ("lshift", Key::LeftShift), ("rshift", Key::RightShift),
+    ("lctrl", Key::LeftControl), ("rctrl", Key::RightControl),
+    ("insert", Key::Insert), ("delete", Key::Delete), ("home", Key::Home), ("end", Key::End),
+    ("pageup", Key::PageUp), ("pagedown", Key::PageDown),
+    ("np0", Key::NumPad0), ("np1", Key::NumPad1), ("np2", Key::NumPad2), ("np3", Key::NumPad3),
+    ("np4", Key::NumPad4), ("np5", Key::NumPad5), ("np6", Key::NumPad6), ("np7", Key::NumPad7),
+    ("np8", Key::NumPad8), ("np9", Key::NumPad9),
+    ("np*", Key::NumPadMul), ("np/", Key::NumPadDiv), ("np+", Key::NumPadAdd),
+    ("np-", Key::NumPadSub), ("np.", Key::NumPadDecimal), ("npenter", Key::NumPadEnter),
+];
 
-    resolution_mode: Resolution,
-    display: [[bool; 64]; 128],
+// opens the screen that rebinds the keys one at a time, escape skips one
+const REBIND_KEY: Key = Key::F9;
 
-    ram: [u8; 0x1000],
-    rpl: [u8; 8],
+// held to run the machine backward, twice as fast as it ran forward
+const REWIND_KEY: Key = Key::Back;
 
-    key_handler: KeyHandler,
+// double and halve the speed of the machine
+const FASTER_KEY: Key = Key::Equal;
+const SLOWER_KEY: Key = Key::Minus;
 
-    call_stack: Vec<u16>,
+// F1-F8 load a save state slot, shift+F1-F8 save to it
+const SLOT_KEYS: [Key; 8] = [
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8,
+];
 
-    pro_counter: u16,
-    mem_pointer: u16,
+#[derive(Parser, Debug)]
+#[command(name = "schip-emu", version, about = "A SUPER-CHIP emulator")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    delay_timer: u8,
-    sound_timer: u8,
+    /// ROM file to run
+    #[arg(required = true)]
+    rom: Option<PathBuf>,
 
-    frame_time: f32,
-    timer_time: f32,
+    /// address the ROM is loaded at (and where execution starts)
+    #[arg(long, default_value = "0x200", value_parser = parse_address)]
+    load_address: u16,
+
+    /// font file loaded over the built-in font at 0x000
+    #[arg(long)]
+    font: Option<PathBuf>,
+
+    /// size of one emulated pixel on screen
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u16).range(1..))]
+    scale: u16,
+
+    /// instructions executed per 60Hz frame [default: 20]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    ipf: Option<u32>,
+
+    /// instructions executed per second instead, rounded to a whole number per frame
+    #[arg(long, conflicts_with = "ipf", value_parser = clap::value_parser!(u32).range(60..))]
+    ips: Option<u32>,
+
+    /// how fast the machine runs against real time, from 0.0625 to 16 (2 is twice as fast)
+    #[arg(long, default_value_t = 1.0)]
+    speed: f32,
+
+    /// color of lit pixels, as RRGGBB hex
+    #[arg(long, value_parser = parse_color)]
+    on_color: Option<olc::Pixel>,
+
+    /// color of unlit pixels, as RRGGBB hex
+    #[arg(long, value_parser = parse_color)]
+    off_color: Option<olc::Pixel>,
+
+    /// machine to emulate (schip, xochip)
+    #[arg(long, default_value_t = Platform::default())]
+    platform: Platform,
+
+    /// quirk preset (chip8, schip10, schip11, modern, xochip), defaults to the platform's
+    #[arg(long)]
+    quirks: Option<QuirkPreset>,
+
+    /// 8XY4-8XYE write VF before the result, so with X = F it keeps the result instead of the flag (no preset does this)
+    #[arg(long)]
+    result_last: bool,
+
+    /// start paused with a debugger prompt on stdin
+    #[arg(long, conflicts_with_all = ["record", "play"])]
+    debug: bool,
+
+    /// how many RPL flags FX75/FX85 reach, defaults to the platform's (8, or 16 on XO-CHIP)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=16))]
+    rpl_flags: Option<u8>,
+
+    /// where RPL flags are kept between runs [default: ~/.local/share/schip-emu/rpl-flags]
+    #[arg(long)]
+    flags_file: Option<PathBuf>,
+
+    /// don't load or save RPL flags
+    #[arg(long, conflicts_with = "flags_file")]
+    no_flags: bool,
+
+    /// boot from this save state instead of a fresh machine
+    #[arg(long)]
+    state: Option<PathBuf>,
+
+    /// megabytes of history kept for rewinding with backspace, 0 turns rewinding off
+    #[arg(long, default_value_t = 16)]
+    rewind_mb: u32,
+
+    /// keyboard layout the key map starts from (qwerty, azerty, numpad)
+    #[arg(long, default_value_t = Layout::default())]
+    layout: Layout,
+
+    /// key map file, with bindings for every ROM and for single ROMs [default: ~/.config/schip-emu/keymap]
+    #[arg(long)]
+    keymap: Option<PathBuf>,
+
+    /// where CXNN's random numbers come from: a seed N, seeded:N, fixed:A,B,C (hex bytes) or vip:N
+    #[arg(long, value_parser = parse_rng)]
+    rng: Option<String>,
+
+    /// record the keys of every frame and the random source to this movie file, written on exit
+    #[arg(long, conflicts_with = "state")]
+    record: Option<PathBuf>,
+
+    /// replay a movie, then hand over to the keyboard when it runs out
+    #[arg(long, conflicts_with_all = ["state", "rng", "platform", "quirks", "result_last", "rpl_flags", "ipf", "ips"])]
+    play: Option<PathBuf>,
+
+    /// pitch of the buzzer in Hz
+    #[arg(long, default_value_t = 440.0)]
+    frequency: f32,
+
+    /// loudness of the buzzer, 0 to 100
+    #[arg(long, default_value_t = 25, value_parser = clap::value_parser!(u8).range(0..=100))]
+    volume: u8,
+
+    /// don't open an audio device
+    #[arg(long)]
+    mute: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a ROM as labeled assembly
+    Disasm {
+        /// ROM file to disassemble
+        rom: PathBuf,
+
+        /// address the ROM is loaded at
+        #[arg(long, default_value = "0x200", value_parser = parse_address)]
+        origin: u16,
+
+        /// assembly dialect (classic or octo)
+        #[arg(long, default_value = "classic")]
+        syntax: Syntax,
+    },
+    /// Assemble classic mnemonic source into a ROM
+    Asm {
+        /// assembly source file
+        source: PathBuf,
+
+        /// where to write the ROM, defaults to the source with a .ch8 extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
 }
 
-fn main() {
-    let mut emulator = Emulator::new();
-    olc::PixelGameEngine::construct(emulator,128,64,5,5).start();
+fn run_command(command: Command) -> ExitCode {
+    match command {
+        Command::Disasm { rom, origin, syntax } => match fs::read(&rom) {
+            Ok(bytes) => {
+                print!("{}", disasm::disassemble(&bytes, origin, syntax));
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("error: could not read {:?}: {}", rom, e);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Asm { source, output } => {
+            let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+            let rom = match assembler::assemble_file(&source) {
+                Ok(rom) => rom,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            match fs::write(&output, &rom) {
+                Ok(()) => {
+                    println!("{:?} => {} bytes.", output, rom.len());
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("error: could not write {:?}: {}", output, e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
 }
 
-impl olc::PGEApplication for Emulator {
+// checked up front so a typo fails before the window opens
+fn parse_rng(s: &str) -> Result<String, String> {
+    random::parse_source(s)?;
+    Ok(s.to_string())
+}
+
+fn parse_color(s: &str) -> Result<olc::Pixel, String> {
+    let hex = s.trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(format!("expected a RRGGBB hex color, got {:?}", s));
+    }
+    let rgb = u32::from_str_radix(hex, 16).map_err(|e| e.to_string())?;
+    Ok(olc::Pixel::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+// the olc-pge window around the emulator core
+struct Frontend {
+    emulator: Emulator,
+    rom: PathBuf, // save state slots are named after it
+    flags: Option<FlagStore>, // written back on exit
+    keymap_file: Option<KeyMapFile>, // the rebinding screen saves the ROM's keys here
+    keymap: KeyMap,
+    keys: [Vec<Key>; 0x10], // the keymap's host keys
+    playing: Option<Movie>, // keys come from here until it runs out
+    recording: Option<(PathBuf, Movie)>, // written on exit
+    frame: usize, // ticks run, the movie's frame
+    rebinding: Option<(u8, KeyMap)>, // the key the rebinding screen is asking for, and the new map so far
+
+    scheduler: Scheduler,
+
+    on_color: olc::Pixel,
+    off_color: olc::Pixel,
+
+    error: Option<EmulatorError>, // set once the emulator crashes, stops execution
+
+    debugger: Option<Debugger>,
+    commands: Option<Receiver<String>>, // lines typed at the debugger prompt
+}
+
+// reads debugger commands on their own thread so the window stays responsive
+fn spawn_prompt() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(feature = "audio")]
+fn open_audio(emulator: &mut Emulator, tone: Tone) {
+    // a machine without sound is still worth running
+    match LiveBeeper::open(tone) {
+        Ok(beeper) => emulator.set_beeper(Box::new(beeper)),
+        Err(e) => eprintln!("warning: no sound: {}", e),
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+fn open_audio(_emulator: &mut Emulator, _tone: Tone) {}
+
+fn print_prompt() {
+    print!("(schip) ");
+    let _ = io::stdout().flush();
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if let Some(command) = args.command {
+        return run_command(command);
+    }
+    let Some(rom) = args.rom else {
+        return ExitCode::FAILURE;
+    };
+    let scale = args.scale as usize;
+    let playing = match args.play.as_ref().map(|path| Movie::load(path).map_err(|e| (path, e))) {
+        Some(Ok(movie)) => Some(movie),
+        Some(Err((path, e))) => {
+            eprintln!("error: could not read {:?}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+        None => None,
+    };
+    let emulator = Emulator::with_config(EmulatorConfig {
+        rom: rom.clone(),
+        load_address: args.load_address,
+        font: args.font,
+        platform: playing.as_ref().map_or(args.platform, |movie| movie.platform),
+        quirks: match &playing {
+            Some(movie) => movie.quirks,
+            None => Quirks {
+                flag_last: !args.result_last,
+                ..args.quirks.unwrap_or(args.platform.default_preset()).into()
+            },
+        },
+        rpl_flags: playing.as_ref().map_or(args.rpl_flags, |movie| Some(movie.rpl_flags.len() as u8)),
+    });
+    let mut emulator = match emulator {
+        Ok(emulator) => emulator,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // a movie brings its own flags, and what it leaves in them isn't the player's
+    let flags_path = match args.flags_file {
+        _ if playing.is_some() => None,
+        Some(path) => Some(path),
+        None if args.no_flags => None,
+        None => FlagStore::default_path(),
+    };
+    let flags = match flags_path.map(|path| FlagStore::open(&path).map_err(|e| (path, e))) {
+        Some(Ok(store)) => Some(store),
+        Some(Err((path, e))) => {
+            // better to play without saved flags than to overwrite the file on exit
+            eprintln!("warning: not using RPL flags, could not read {:?}: {}", path, e);
+            None
+        }
+        None => None,
+    };
+    if let Some(movie) = &playing {
+        emulator.set_rpl_flags(&movie.rpl_flags);
+    } else if let Some(saved) = flags.as_ref().and_then(|store| store.get(emulator.rom_hash())) {
+        emulator.set_rpl_flags(saved);
+    }
+    if let Some(path) = &args.state {
+        let loaded = fs::read(path)
+            .map_err(|error| EmulatorError::Io { path: path.clone(), error })
+            .and_then(|state| emulator.load_state(&state));
+        if let Err(e) = loaded {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+    emulator.set_rewind_budget(args.rewind_mb as usize * 1024 * 1024);
+    if !args.mute {
+        let tone = Tone {
+            frequency: args.frequency,
+            volume: args.volume as f32 / 100.0,
+        };
+        open_audio(&mut emulator, tone);
+    }
+    let keymap_path = args.keymap.or_else(KeyMapFile::default_path);
+    let keymap_file = match keymap_path.map(|path| KeyMapFile::open(&path).map_err(|e| (path, e))) {
+        Some(Ok(file)) => Some(file),
+        Some(Err((path, e))) => {
+            eprintln!("warning: using the {} layout, could not read {:?}: {}", args.layout, path, e);
+            None
+        }
+        None => None,
+    };
+    let keymap = match &keymap_file {
+        Some(file) => file.get(emulator.rom_hash(), args.layout),
+        None => KeyMap::from_layout(args.layout),
+    };
+    let mut scheduler = match (&playing, args.ips) {
+        (Some(movie), _) => Scheduler::new(movie.ipf),
+        (None, Some(ips)) => Scheduler::from_ips(ips),
+        (None, None) => Scheduler::new(args.ipf.unwrap_or(20)),
+    };
+    scheduler.set_speed(args.speed);
+    if let Some(rng) = args.rng.or(playing.as_ref().map(|movie| movie.rng.clone())) {
+        match random::parse_source(&rng) {
+            Ok(rng) => emulator.set_rng(rng),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if playing.as_ref().is_some_and(|movie| movie.rom_hash != emulator.rom_hash()) {
+        eprintln!("warning: the movie was recorded with a different ROM");
+    }
+    let recording = args
+        .record
+        .map(|path| (path, Movie::new(&emulator, scheduler.get_ipf())));
+    let frontend = Frontend {
+        emulator,
+        rom,
+        flags,
+        keymap_file,
+        keys: host_keys(&keymap),
+        keymap,
+        playing,
+        recording,
+        frame: 0,
+        rebinding: None,
+        scheduler,
+        on_color: args.on_color.unwrap_or(ON_COLOR),
+        off_color: args.off_color.unwrap_or(OFF_COLOR),
+        error: None,
+        debugger: args.debug.then(|| Debugger::new(true)),
+        commands: args.debug.then(spawn_prompt),
+    };
+    olc::PixelGameEngine::construct(frontend, 128, 64, scale, scale).start();
+    ExitCode::SUCCESS
+}
+
+impl olc::PGEApplication for Frontend {
     const APP_NAME: &'static str = "SuperChip Emulator";
     fn on_user_create(&mut self, _pge: &mut PixelGameEngine) -> bool {
-        for i in 0..4096 {
-            if i % 16 == 0 {
-                println!();
-                print!("{:#05X} => ",i);
-            }
-            print!("{:#04X} ",self.get_ram(i));
+        if let Some(debugger) = &self.debugger {
+            println!("{}", debugger.registers(&self.emulator));
+            print_prompt();
         }
-        println!();
         true
     }
 
     fn on_user_update(&mut self, pge: &mut PixelGameEngine, elapsed_time: f32) -> bool {
-        self.key_handler.update_keys(pge);
-
-        self.timer_time += elapsed_time;
-        if self.timer_time >= 1.0/60.0 {
-            self.delay_timer = if self.delay_timer == 0 {0} else {self.delay_timer-1};
-            self.sound_timer = if self.sound_timer == 0 {0} else {self.sound_timer-1};
-            self.timer_time = 0.0;
-        }
-
-        if self.key_handler.key_hold == 0x10 {
-            self.frame_time += elapsed_time;
-            if self.frame_time >= FRAME_TIME {
-                let redraw = self.execute();
-                if redraw { self.draw_to_screen(pge); }
-                self.frame_time = 0.0;
-            }
-        } else { // wait for a key in this case
-            match self.key_handler.key_block_pressed(pge) {
-                Some(key) => {
-                    self.set_register(self.key_handler.key_hold,key);
-                    self.key_handler.key_hold = 0x10;
+        // slots work on the crash screen too, loading one is a way back out
+        self.handle_slot_keys(pge);
+        if self.error.is_some() {
+            // keep the window open on the crash screen
+            return true;
+        }
+
+        if pge.get_key(REBIND_KEY).pressed {
+            self.toggle_rebinding(pge);
+        }
+        if self.rebinding.is_some() {
+            // the machine waits until the keys are bound
+            self.handle_rebinding(pge);
+            return true;
+        }
+
+        // a movie has to run straight through, so it can't be rewound
+        let rewinding = pge.get_key(REWIND_KEY).held && !self.movie_running();
+        let mut held = [false; 0x10];
+        for (i, hosts) in self.keys.iter().enumerate() {
+            held[i] = hosts.iter().any(|key| pge.get_key(*key).held);
+        }
+
+        if let (Some(debugger), Some(commands)) = (&mut self.debugger, &self.commands) {
+            let mut ran_command = false;
+            for line in commands.try_iter() {
+                let output = debugger.command(&line, &mut self.emulator);
+                if !output.is_empty() {
+                    println!("{}", output);
                 }
-                None => ()
+                print_prompt();
+                ran_command = true;
+            }
+            if ran_command {
+                self.draw_to_screen(pge);
+            }
+        }
+        let paused = self.debugger.as_ref().is_some_and(|d| d.is_paused());
+        if paused {
+            // a key wait can still be stepped past, but a movie's keys are its own
+            if self.playing.is_none() {
+                self.emulator.update_keys(held);
+            }
+            return true;
+        }
+
+        let ticks = self.scheduler.advance(elapsed_time);
+        if rewinding {
+            let steps = 2 * ticks as u64 * self.scheduler.get_ipf() as u64;
+            if steps > 0 && self.emulator.step_back(steps) > 0 {
+                self.draw_to_screen(pge);
+            }
+            return true;
+        }
+        self.handle_speed_keys(pge);
+        for _ in 0..ticks {
+            // the keys change once a tick, the same as in a movie
+            let keys = self.tick_keys(held);
+            self.emulator.update_keys(keys);
+            if !self.run_tick(pge) {
+                break;
+            }
+        }
+        true
+    }
+
+    fn on_user_destroy(&mut self) -> bool {
+        if let Some((path, movie)) = &self.recording {
+            match movie.save(path) {
+                Ok(()) => println!("recorded {} frames to {:?}", movie.len(), path),
+                Err(e) => eprintln!("error: could not write {:?}: {}", path, e),
+            }
+        }
+        if let Some(store) = &mut self.flags {
+            store.set(self.emulator.rom_hash(), self.emulator.rpl_flags());
+            if let Err(e) = store.save() {
+                eprintln!("error: could not write {:?}: {}", store.path(), e);
             }
         }
         true
     }
 }
 
-impl Emulator {
-    pub fn new() -> Emulator {
-        let mut registers = Vec::new();
-        for i in 0..0x10 as u8 {
-            registers.push(components::Register::new(i));
-        }
-
-        let mut ret = Self {
-            registers,
-            resolution_mode: Resolution::Low,
-            display: [[false; 64]; 128],
-            ram: [0u8; 0x1000],
-            rpl: [0u8; 8],
-            key_handler: KeyHandler::new(),
-            call_stack: Vec::new(),
-            pro_counter: 0x200,
-            mem_pointer: 0x000,
-            delay_timer: 0x00,
-            sound_timer: 0x00,
-            frame_time: 0.0,
-            timer_time: 0.0,
+// the olc keys behind a key map, names it doesn't know are left out
+fn host_keys(keymap: &KeyMap) -> [Vec<Key>; 0x10] {
+    std::array::from_fn(|i| {
+        keymap
+            .get(i as u8)
+            .iter()
+            .filter_map(|name| match HOST_KEYS.iter().find(|(host, _)| host == name) {
+                Some((_, key)) => Some(*key),
+                None => {
+                    eprintln!("warning: key {:X} is bound to unknown key {:?}", i, name);
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+impl Frontend {
+    fn movie_running (&self) -> bool {
+        self.playing.is_some() || self.recording.is_some()
+    }
+
+    // the keys for the next tick, from the movie while one plays
+    fn tick_keys (&mut self, held: [bool; 0x10]) -> [bool; 0x10] {
+        let keys = match &self.playing {
+            Some(movie) => match movie.frame(self.frame) {
+                Some(keys) => keys,
+                None => {
+                    println!("the movie is over, over to the keyboard");
+                    self.playing = None;
+                    held
+                }
+            },
+            None => held,
         };
-        ret.load_rom(0x000,"system/font.bin");
-        ret.load_rom(0x200,"demo/TETRIS.ch8");
-        return ret
+        if let Some((_, movie)) = &mut self.recording {
+            movie.push(keys);
+        }
+        self.frame += 1;
+        keys
+    }
+
+    fn toggle_rebinding (&mut self, pge: &mut olc::PixelGameEngine) {
+        if self.rebinding.take().is_some() {
+            // leaving early keeps the old keys
+            println!("rebinding cancelled");
+            self.draw_to_screen(pge);
+            return;
+        }
+        self.rebinding = Some((0, self.keymap.clone()));
+        self.draw_rebinding(pge);
+    }
+
+    fn handle_rebinding (&mut self, pge: &mut olc::PixelGameEngine) {
+        let Some((key, keymap)) = &mut self.rebinding else { return };
+        if pge.get_key(Key::Escape).pressed {
+            *key += 1;
+        } else if let Some((name, _)) = HOST_KEYS.iter().find(|(_, host)| pge.get_key(*host).pressed) {
+            keymap.set(*key, &[name.to_string()]);
+            *key += 1;
+        } else {
+            return;
+        }
+        if *key < 0x10 {
+            self.draw_rebinding(pge);
+            return;
+        }
+        let Some((_, keymap)) = self.rebinding.take() else { return };
+        self.keymap = keymap;
+        self.keys = host_keys(&self.keymap);
+        if let Some(file) = &mut self.keymap_file {
+            file.set(self.emulator.rom_hash(), &self.keymap);
+            match file.save() {
+                Ok(()) => println!("saved the keys for this ROM to {:?}", file.path()),
+                Err(e) => eprintln!("error: could not write {:?}: {}", file.path(), e),
+            }
+        }
+        self.draw_to_screen(pge);
+    }
+
+    fn draw_rebinding (&self, pge: &mut olc::PixelGameEngine) {
+        let Some((key, keymap)) = &self.rebinding else { return };
+        let lines = [
+            String::from("REBIND KEYS"),
+            String::from("ESC SKIPS"),
+            String::new(),
+            format!("KEY {:X} IS", key),
+            keymap.get(*key).join(" ").to_uppercase(),
+            String::new(),
+            String::from("PRESS A KEY"),
+        ];
+        pge.clear(self.off_color);
+        for (i, line) in lines.iter().enumerate() {
+            pge.draw_string(0, i as i32 * 8 + 4, line, self.on_color);
+        }
+    }
+
+    // one 60Hz tick: the instruction budget, then the timers.
+    // false once the machine has crashed or the debugger has stopped it
+    fn run_tick (&mut self, pge: &mut olc::PixelGameEngine) -> bool {
+        let mut redraw = false;
+        let mut running = true;
+        for _ in 0..self.scheduler.get_ipf() {
+            if self.emulator.waiting_for_key() || self.emulator.waiting_for_vblank() {
+                break;
+            }
+            let result = match &mut self.debugger {
+                Some(debugger) => debugger.execute(&mut self.emulator),
+                None => self.emulator.execute(),
+            };
+            match result {
+                Ok(drew) => redraw |= drew,
+                Err(e) if self.debugger.is_some() => {
+                    // the debugger has paused, leave the machine to be inspected
+                    println!("\nerror: {}", e);
+                    print_prompt();
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    self.error = Some(e);
+                    self.draw_error(pge);
+                    return false;
+                }
+            }
+            if let Some(debugger) = &mut self.debugger {
+                if let Some(reason) = debugger.take_stop_reason() {
+                    println!("\n{}\n{}", reason, debugger.registers(&self.emulator));
+                    print_prompt();
+                }
+                if debugger.is_paused() {
+                    running = false;
+                    break;
+                }
+            }
+        }
+        if redraw {
+            self.draw_to_screen(pge);
+        }
+        self.emulator.tick_timers();
+        running
     }
 
-    pub fn draw_to_screen (&self, pge: &mut olc::PixelGameEngine) {
-        pge.clear(OFF_COLOR);
+    fn handle_speed_keys (&mut self, pge: &mut olc::PixelGameEngine) {
+        let speed = self.scheduler.get_speed();
+        if pge.get_key(FASTER_KEY).pressed {
+            self.scheduler.set_speed(speed * 2.0);
+        } else if pge.get_key(SLOWER_KEY).pressed {
+            self.scheduler.set_speed(speed / 2.0);
+        } else {
+            return;
+        }
+        println!("speed x{}", self.scheduler.get_speed());
+    }
+
+    fn handle_slot_keys (&mut self, pge: &mut olc::PixelGameEngine) {
+        let shift = [Key::Shift, Key::LeftShift, Key::RightShift]
+            .iter()
+            .any(|key| pge.get_key(*key).held);
+        for (i, key) in SLOT_KEYS.iter().enumerate() {
+            if !pge.get_key(*key).pressed {
+                continue;
+            }
+            let slot = i + 1;
+            let path = self.rom.with_extension(format!("state{}", slot));
+            if shift {
+                match fs::write(&path, self.emulator.save_state()) {
+                    Ok(()) => println!("saved slot {} to {:?}", slot, path),
+                    Err(e) => eprintln!("error: could not write {:?}: {}", path, e),
+                }
+                continue;
+            }
+            if self.movie_running() {
+                eprintln!("error: can't load a state in the middle of a movie");
+                continue;
+            }
+            let loaded = fs::read(&path)
+                .map_err(|error| EmulatorError::Io { path: path.clone(), error })
+                .and_then(|state| self.emulator.load_state(&state));
+            match loaded {
+                Ok(()) => {
+                    println!("loaded slot {} from {:?}", slot, path);
+                    self.error = None;
+                    self.draw_to_screen(pge);
+                }
+                Err(e) => eprintln!("error: {}", e),
+            }
+        }
+    }
+
+    fn draw_error (&self, pge: &mut olc::PixelGameEngine) {
+        // the 8x8 font fits 16 characters across the 128 pixel screen
+        let Some(error) = &self.error else { return };
+        self.draw_to_screen(pge);
+        let mut lines = vec![String::from("CRASHED:"), String::new()];
+        for word in error.to_string().split_whitespace() {
+            let line = lines.last_mut().unwrap();
+            if line.is_empty() {
+                line.push_str(word);
+            } else if line.len() + 1 + word.len() <= 16 {
+                line.push(' ');
+                line.push_str(word);
+            } else {
+                lines.push(word.to_string());
+            }
+        }
+        for (i, line) in lines.iter().enumerate() {
+            pge.fill_rect(0, i as i32 * 8, 128, 8, self.off_color);
+            pge.draw_string(0, i as i32 * 8, line, ERROR_COLOR);
+        }
+    }
+
+    fn draw_to_screen (&self, pge: &mut olc::PixelGameEngine) {
+        let colors = [self.off_color, self.on_color, PLANE2_COLOR, BOTH_PLANES_COLOR];
+        pge.clear(self.off_color);
         for x in 0..128 {
             for y in 0..64 {
-                if self.display[x][y] {
-                    pge.draw(x as i32, y as i32, ON_COLOR);
-                } else {
-                    pge.draw(x as i32, y as i32, OFF_COLOR);
-                }
+                let color = colors[self.emulator.pixel(x, y) as usize];
+                pge.draw(x as i32, y as i32, color);
             }
         }
     }
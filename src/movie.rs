@@ -0,0 +1,152 @@
+use crate::quirks::{Platform, Quirks};
+use crate::random;
+use crate::savestate::{bits_to_keys, keys_to_bits, quirks_from_bytes, quirks_to_bytes};
+use crate::Emulator;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// the keys held on every 60Hz frame of a run from power-on, with everything else
+// it takes to run it again the same way. a text file, so it can go in a bug report:
+//
+//     schip-movie 4
+//     rom <hash of the ROM, 16 hex digits>
+//     platform schip
+//     quirks <one digit per quirk, as in save states>
+//     rpl <the RPL flags at power-on, 2 hex digits each, as many as FX75/FX85 reach>
+//     rng <where CXNN's numbers come from, see random.rs>
+//     ipf <instructions per frame>
+//     <frames> <held keys, 4 hex digits>   (one line per run of identical frames)
+
+const MOVIE_MAGIC: &str = "schip-movie";
+const MOVIE_VERSION: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64, // a movie only makes sense with the ROM it was recorded on
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub rpl_flags: Vec<u8>, // saved flags change what a ROM does from the first frame
+    pub rng: String, // the random source at power-on, described
+    pub ipf: u32,
+    frames: Vec<u16>, // held keys, one bit a key
+}
+
+impl Movie {
+    // an empty movie of a machine that was just switched on
+    pub fn new(emulator: &Emulator, ipf: u32) -> Movie {
+        Movie {
+            rom_hash: emulator.rom_hash(),
+            platform: emulator.platform(),
+            quirks: emulator.quirks(),
+            rpl_flags: emulator.rpl_flags().to_vec(),
+            rng: emulator.rng_description(),
+            ipf,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, keys: [bool; 0x10]) {
+        self.frames.push(keys_to_bits(&keys));
+    }
+    pub fn frame(&self, frame: usize) -> Option<[bool; 0x10]> {
+        self.frames.get(frame).map(|bits| bits_to_keys(*bits))
+    }
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = String::new();
+        let _ = writeln!(out, "{} {}", MOVIE_MAGIC, MOVIE_VERSION);
+        let _ = writeln!(out, "rom {:016x}", self.rom_hash);
+        let _ = writeln!(out, "platform {}", self.platform);
+        let quirks: String = quirks_to_bytes(&self.quirks).iter().map(|q| q.to_string()).collect();
+        let _ = writeln!(out, "quirks {}", quirks);
+        let rpl: String = self.rpl_flags.iter().map(|flag| format!("{:02x}", flag)).collect();
+        let _ = writeln!(out, "rpl {}", rpl);
+        let _ = writeln!(out, "rng {}", self.rng);
+        let _ = writeln!(out, "ipf {}", self.ipf);
+        let mut frames = self.frames.iter().peekable();
+        while let Some(keys) = frames.next() {
+            let mut run = 1;
+            while frames.next_if_eq(&keys).is_some() {
+                run += 1;
+            }
+            let _ = writeln!(out, "{} {:04x}", run, keys);
+        }
+        fs::write(path, out)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Movie> {
+        let source = fs::read_to_string(path)?;
+        let mut lines = source.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let bad = |number: usize, message: &str| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, message))
+        };
+        // the header is one `<name> <value>` line each, in order
+        let mut header = |name: &str| -> io::Result<(usize, &str)> {
+            let (number, line) = lines
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("no `{}` line", name)))?;
+            match line.trim().split_once(' ') {
+                Some((found, value)) if found == name => Ok((number, value.trim())),
+                _ => Err(bad(number, &format!("expected `{} ...`, got {:?}", name, line))),
+            }
+        };
+
+        let (number, version) = header(MOVIE_MAGIC)?;
+        if version.parse() != Ok(MOVIE_VERSION) {
+            return Err(bad(number, &format!("unsupported movie version {:?}", version)));
+        }
+        let (number, rom) = header("rom")?;
+        let rom_hash = u64::from_str_radix(rom, 16).map_err(|_| bad(number, "bad ROM hash"))?;
+        let (number, platform) = header("platform")?;
+        let platform = platform.parse().map_err(|e: String| bad(number, &e))?;
+        let (number, quirks) = header("quirks")?;
+        let digits: Vec<u8> = quirks.bytes().map(|digit| digit.wrapping_sub(b'0')).collect();
+        let quirks = <[u8; 8]>::try_from(digits)
+            .ok()
+            .and_then(|bytes| quirks_from_bytes(bytes).ok())
+            .ok_or_else(|| bad(number, "bad quirks"))?;
+        let (number, rpl) = header("rpl")?;
+        let rpl_flags = (0..rpl.len())
+            .step_by(2)
+            .map(|i| rpl.get(i..i + 2).and_then(|flag| u8::from_str_radix(flag, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .filter(|flags| (1..=16).contains(&flags.len()))
+            .ok_or_else(|| bad(number, "bad RPL flags"))?;
+        let (number, rng) = header("rng")?;
+        random::parse_source(rng).map_err(|e| bad(number, &e))?;
+        let rng = rng.to_string();
+        let (number, ipf) = header("ipf")?;
+        let ipf = match ipf.parse() {
+            Ok(ipf) if ipf > 0 => ipf,
+            _ => return Err(bad(number, "bad instructions per frame")),
+        };
+
+        let mut frames = Vec::new();
+        for (number, line) in lines {
+            let run = line.trim().split_once(' ').and_then(|(run, keys)| {
+                Some((run.parse::<usize>().ok()?, u16::from_str_radix(keys.trim(), 16).ok()?))
+            });
+            let Some((run, keys)) = run else {
+                return Err(bad(number, &format!("expected `<frames> <keys>`, got {:?}", line)));
+            };
+            frames.extend(std::iter::repeat_n(keys, run));
+        }
+        Ok(Movie {
+            rom_hash,
+            platform,
+            quirks,
+            rpl_flags,
+            rng,
+            ipf,
+            frames,
+        })
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuirkPreset {
+    Chip8,   // the original COSMAC VIP interpreter
+    Schip10, // SUPER-CHIP 1.0 on the HP48
+    #[default]
+    Schip11, // SUPER-CHIP 1.1 on the HP48
+    Modern,  // what most modern SCHIP interpreters do
+    XoChip,  // Octo's XO-CHIP
+}
+impl QuirkPreset {
+    pub const NAMES: [&'static str; 5] = ["chip8", "schip10", "schip11", "modern", "xochip"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuirkPreset::Chip8 => "chip8",
+            QuirkPreset::Schip10 => "schip10",
+            QuirkPreset::Schip11 => "schip11",
+            QuirkPreset::Modern => "modern",
+            QuirkPreset::XoChip => "xochip",
+        }
+    }
+}
+impl fmt::Display for QuirkPreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+impl FromStr for QuirkPreset {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" | "vip" => Ok(QuirkPreset::Chip8),
+            "schip10" | "schip1.0" => Ok(QuirkPreset::Schip10),
+            "schip11" | "schip1.1" | "schip" => Ok(QuirkPreset::Schip11),
+            "modern" => Ok(QuirkPreset::Modern),
+            "xochip" | "xo-chip" | "octo" => Ok(QuirkPreset::XoChip),
+            _ => Err(format!(
+                "unknown quirk preset {:?} (expected one of: {})",
+                s,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+// the machine being emulated, which decides the instruction set and memory size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    #[default]
+    Schip,  // SUPER-CHIP, 4K of RAM
+    XoChip, // XO-CHIP, 64K of RAM, two bitplanes and sampled audio
+}
+impl Platform {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Schip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+    pub fn ram_size(&self) -> usize {
+        match self {
+            Platform::Schip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+    // FX75/FX85 reach 8 flags on the HP48, XO-CHIP has 16
+    pub fn rpl_flags(&self) -> u8 {
+        match self {
+            Platform::Schip => 8,
+            Platform::XoChip => 16,
+        }
+    }
+    // the quirks used when none are asked for
+    pub fn default_preset(&self) -> QuirkPreset {
+        match self {
+            Platform::Schip => QuirkPreset::Schip11,
+            Platform::XoChip => QuirkPreset::XoChip,
+        }
+    }
+}
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+impl FromStr for Platform {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "schip" | "superchip" | "super-chip" => Ok(Platform::Schip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform {:?} (expected schip or xochip)", s)),
+        }
+    }
+}
+
+// where FX55/FX65 leave I afterwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    Unchanged,  // I is left alone (SCHIP 1.1)
+    ByX,        // I += X (SCHIP 1.0)
+    ByXPlusOne, // I += X + 1 (CHIP-8)
+}
+
+// when DXYN holds the CPU until the next 60Hz tick (vertical blank)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayWait {
+    Never,         // draws go straight through (modern interpreters)
+    Always,        // every draw waits (the COSMAC VIP)
+    LowResolution, // only low resolution draws wait (SCHIP on the HP48)
+}
+
+// what becomes of the part of a sprite past the screen edge, DXYN and DXY0 alike.
+// the position it's drawn at always wraps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteEdges {
+    Wrap, // comes back in on the opposite side (XO-CHIP)
+    Clip, // cut off (SCHIP 1.1 and the COSMAC VIP)
+}
+
+// the behaviors that differ between interpreters for the same opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool,   // 8XY6/8XYE shift VY into VX instead of shifting VX in place
+    pub memory_increment: MemoryIncrement,
+    pub jump_uses_vx: bool,    // BXNN jumps to XNN + VX instead of NNN + V0
+    pub logic_resets_vf: bool, // 8XY1/8XY2/8XY3 set VF to 0
+    pub sprite_edges: SpriteEdges,
+    pub display_wait: DisplayWait,
+    pub key_release: bool,     // FX0A finishes when the key is let go instead of as it goes down
+    // 8XY4-8XYE write VF after VX, so with X = F it ends up holding the flag. true in every
+    // preset, since every interpreter they follow does it; only --result-last turns it off
+    pub flag_last: bool,
+}
+impl Quirks {
+    pub fn from_preset(preset: QuirkPreset) -> Quirks {
+        match preset {
+            QuirkPreset::Chip8 => Quirks {
+                shift_uses_vy: true,
+                memory_increment: MemoryIncrement::ByXPlusOne,
+                jump_uses_vx: false,
+                logic_resets_vf: true,
+                sprite_edges: SpriteEdges::Clip,
+                display_wait: DisplayWait::Always,
+                key_release: true,
+                flag_last: true,
+            },
+            QuirkPreset::Schip10 => Quirks {
+                shift_uses_vy: false,
+                memory_increment: MemoryIncrement::ByX,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                sprite_edges: SpriteEdges::Clip,
+                display_wait: DisplayWait::LowResolution,
+                key_release: false,
+                flag_last: true,
+            },
+            QuirkPreset::Schip11 => Quirks {
+                shift_uses_vy: false,
+                memory_increment: MemoryIncrement::Unchanged,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                sprite_edges: SpriteEdges::Clip,
+                display_wait: DisplayWait::LowResolution,
+                key_release: false,
+                flag_last: true,
+            },
+            // SCHIP 1.1 as Octo and most interpreters since run it, without the HP48's timing
+            QuirkPreset::Modern => Quirks {
+                shift_uses_vy: false,
+                memory_increment: MemoryIncrement::Unchanged,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                sprite_edges: SpriteEdges::Clip,
+                display_wait: DisplayWait::Never,
+                key_release: false,
+                flag_last: true,
+            },
+            QuirkPreset::XoChip => Quirks {
+                shift_uses_vy: true,
+                memory_increment: MemoryIncrement::ByXPlusOne,
+                jump_uses_vx: false,
+                logic_resets_vf: false,
+                sprite_edges: SpriteEdges::Wrap,
+                display_wait: DisplayWait::Never,
+                key_release: true,
+                flag_last: true,
+            },
+        }
+    }
+}
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::from_preset(QuirkPreset::default())
+    }
+}
+impl From<QuirkPreset> for Quirks {
+    fn from(preset: QuirkPreset) -> Quirks {
+        Quirks::from_preset(preset)
+    }
+}
@@ -0,0 +1,123 @@
+// where CXNN gets its numbers. every source can describe where it is as a string
+// that parse_source turns back into the same source, which is how save states and
+// movies carry it:
+//
+//     seeded:N          a PRNG, N is its state (any u64 starts one)
+//     fixed:A,B,C@I     the hex bytes A, B, C over and over, next up is the I-th
+//     vip:N             the COSMAC VIP interpreter's generator, N is its counter (R9)
+
+pub trait RandomSource {
+    // the next byte, before CXNN masks it. ram is there for sources that read it
+    fn next_byte(&mut self, ram: &[u8]) -> u8;
+    fn describe(&self) -> String;
+}
+
+// SplitMix64, small and the same everywhere, so a seed means the same numbers
+// on every build and platform
+pub struct Seeded {
+    state: u64,
+}
+impl Seeded {
+    pub fn new(seed: u64) -> Seeded {
+        Seeded { state: seed }
+    }
+    // a seed nobody picked
+    pub fn from_entropy() -> Seeded {
+        Seeded::new(rand::random())
+    }
+}
+impl RandomSource for Seeded {
+    fn next_byte(&mut self, _ram: &[u8]) -> u8 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+    fn describe(&self) -> String {
+        format!("seeded:{}", self.state)
+    }
+}
+
+// the same bytes in a loop, for tests that need to know what CXNN rolls
+pub struct FixedSequence {
+    bytes: Vec<u8>,
+    next: usize,
+}
+impl FixedSequence {
+    // an empty sequence rolls nothing but zeros
+    pub fn new(bytes: Vec<u8>) -> FixedSequence {
+        FixedSequence { bytes, next: 0 }
+    }
+}
+impl RandomSource for FixedSequence {
+    fn next_byte(&mut self, _ram: &[u8]) -> u8 {
+        let Some(byte) = self.bytes.get(self.next) else {
+            return 0;
+        };
+        self.next = (self.next + 1) % self.bytes.len();
+        *byte
+    }
+    fn describe(&self) -> String {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("fixed:{}@{}", bytes.join(","), self.next)
+    }
+}
+
+// the VIP interpreter counts up in R9 on every CXNN, adds the byte of its own code
+// page (0x100-0x1FF) at the low half of the count into the high half, and rolls
+// that. the page is read from RAM where the VIP kept it, so loading a dump of the
+// interpreter at 0x000 (--font) rolls the VIP's own numbers. without one that page
+// is empty and every roll would be the same, so the first page of the program
+// stands in for it
+const VIP_CODE_PAGE: usize = 0x100;
+pub struct CosmacVip {
+    r9: u16,
+}
+impl CosmacVip {
+    pub fn new(r9: u16) -> CosmacVip {
+        CosmacVip { r9 }
+    }
+}
+impl RandomSource for CosmacVip {
+    fn next_byte(&mut self, ram: &[u8]) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let [high, low] = self.r9.to_be_bytes();
+        let interpreter = ram.get(VIP_CODE_PAGE..VIP_CODE_PAGE + 0x100).unwrap_or_default();
+        let page = if interpreter.iter().any(|byte| *byte != 0) { VIP_CODE_PAGE } else { 0x200 };
+        let code = ram.get(page + low as usize).copied().unwrap_or(0);
+        let byte = code.wrapping_add(high);
+        self.r9 = u16::from_be_bytes([byte, low]);
+        byte
+    }
+    fn describe(&self) -> String {
+        format!("vip:{}", self.r9)
+    }
+}
+
+// the inverse of describe, a bare number is a seed
+pub fn parse_source(s: &str) -> Result<Box<dyn RandomSource>, String> {
+    let bad = || format!("{:?} is not a random source (expected N, seeded:N, fixed:A,B,C or vip:N)", s);
+    let (kind, value) = s.split_once(':').unwrap_or(("seeded", s));
+    match kind {
+        "seeded" => Ok(Box::new(Seeded::new(value.parse().map_err(|_| bad())?))),
+        "vip" => Ok(Box::new(CosmacVip::new(value.parse().map_err(|_| bad())?))),
+        "fixed" => {
+            let (bytes, next) = match value.split_once('@') {
+                Some((bytes, next)) => (bytes, next.parse().map_err(|_| bad())?),
+                None => (value, 0),
+            };
+            let bytes = bytes
+                .split(',')
+                .filter(|byte| !byte.is_empty())
+                .map(|byte| u8::from_str_radix(byte.trim_start_matches("0x"), 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| bad())?;
+            if next > 0 && next >= bytes.len() {
+                return Err(bad());
+            }
+            Ok(Box::new(FixedSequence { bytes, next }))
+        }
+        _ => Err(bad()),
+    }
+}
@@ -0,0 +1,226 @@
+use crate::savestate::bits_to_keys;
+use crate::Emulator;
+use std::collections::VecDeque;
+use std::mem;
+
+// stepping backward restores the newest snapshot before the moment wanted and
+// replays the inputs logged since, so snapshots only need to be taken now and then
+const SNAPSHOT_INTERVAL: u64 = 256; // instructions between snapshots
+
+// everything from outside the machine that changes what it does next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Input {
+    Keys(u16), // update_keys, one bit per key
+    Tick,      // tick_timers
+}
+
+struct Snapshot {
+    step: u64,      // taken just before this instruction ran
+    state: Vec<u8>, // a save state, compressed
+}
+
+// a bounded history of the machine, oldest first
+pub(crate) struct Rewind {
+    budget: usize, // bytes the snapshots and inputs may take up
+    size: usize,
+    step: u64, // instructions run since the history started
+    snapshots: VecDeque<Snapshot>,
+    inputs: VecDeque<(u64, Input)>, // with the instruction they came before
+}
+
+impl Rewind {
+    fn new(budget: usize) -> Rewind {
+        Rewind {
+            budget,
+            size: 0,
+            step: 0,
+            snapshots: VecDeque::new(),
+            inputs: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.snapshots.clear();
+        self.inputs.clear();
+        self.size = 0;
+    }
+
+    fn push_input(&mut self, input: Input) {
+        self.inputs.push_back((self.step, input));
+        self.size += mem::size_of::<(u64, Input)>();
+        self.trim();
+    }
+
+    // drops the oldest snapshots, and the inputs only they needed, until it fits
+    fn trim(&mut self) {
+        while self.size > self.budget && self.snapshots.len() > 1 {
+            if let Some(oldest) = self.snapshots.pop_front() {
+                self.size -= snapshot_size(&oldest);
+            }
+            let start = self.oldest_step();
+            while self.inputs.front().is_some_and(|(step, _)| *step < start) {
+                self.inputs.pop_front();
+                self.size -= mem::size_of::<(u64, Input)>();
+            }
+        }
+        // the inputs after a lone snapshot can't be dropped, so start over from a new one
+        if self.size > self.budget {
+            self.clear();
+        }
+    }
+
+    fn oldest_step(&self) -> u64 {
+        self.snapshots.front().map_or(self.step, |snapshot| snapshot.step)
+    }
+
+    // forgets everything after step, which becomes the present
+    fn truncate(&mut self, step: u64) {
+        while self.snapshots.back().is_some_and(|snapshot| snapshot.step > step) {
+            if let Some(newest) = self.snapshots.pop_back() {
+                self.size -= snapshot_size(&newest);
+            }
+        }
+        while self.inputs.back().is_some_and(|(at, _)| *at > step) {
+            self.inputs.pop_back();
+            self.size -= mem::size_of::<(u64, Input)>();
+        }
+        self.step = step;
+    }
+}
+
+fn snapshot_size(snapshot: &Snapshot) -> usize {
+    mem::size_of::<Snapshot>() + snapshot.state.len()
+}
+
+// runs of zeros become a zero and the run length, RAM and the display are mostly empty
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() / 4);
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != 0 {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let run = bytes[i..].iter().take(255).take_while(|byte| **byte == 0).count();
+        out.push(0);
+        out.push(run as u8);
+        i += run;
+    }
+    out
+}
+
+fn expand(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() * 4);
+    let mut iter = bytes.iter();
+    while let Some(byte) = iter.next() {
+        match byte {
+            0 => out.resize(out.len() + *iter.next().unwrap_or(&0) as usize, 0),
+            _ => out.push(*byte),
+        }
+    }
+    out
+}
+
+impl Emulator {
+    // keeps up to budget bytes of history to step back through, 0 turns it off
+    pub fn set_rewind_budget(&mut self, budget: usize) {
+        if budget == 0 {
+            self.rewind = None;
+            return;
+        }
+        match &mut self.rewind {
+            Some(rewind) => {
+                rewind.budget = budget;
+                rewind.trim();
+            }
+            None => self.rewind = Some(Rewind::new(budget)),
+        }
+    }
+
+    // how many instructions step_back can undo
+    pub fn rewind_depth(&self) -> u64 {
+        self.rewind
+            .as_ref()
+            .map_or(0, |rewind| rewind.step - rewind.oldest_step())
+    }
+
+    // undoes up to steps instructions, with the inputs that came between them,
+    // and returns how many were undone
+    pub fn step_back(&mut self, steps: u64) -> u64 {
+        let Some(mut rewind) = self.rewind.take() else {
+            return 0;
+        };
+        let target = rewind.step.saturating_sub(steps).max(rewind.oldest_step());
+        let Some(snapshot) = rewind.snapshots.iter().rev().find(|snapshot| snapshot.step <= target) else {
+            self.rewind = Some(rewind);
+            return 0;
+        };
+        let start = snapshot.step;
+        self.load_state(&expand(&snapshot.state))
+            .expect("rewind snapshots are always valid save states");
+
+        // the history is out of self while replaying, so nothing is logged twice.
+        // the snapshot has the random source in it, so CXNN rolls the same numbers again
+        let mut inputs = rewind.inputs.iter().skip_while(|(step, _)| *step < start).peekable();
+        for step in start..=target {
+            // inputs before the snapshot's own instruction are already in it
+            while let Some((_, input)) = inputs.next_if(|(at, _)| *at == step) {
+                if step > start {
+                    self.replay_input(*input);
+                }
+            }
+            if step == target {
+                break;
+            }
+            // errors happened the first time round too
+            let _ = self.execute_instruction();
+        }
+        // watches were tripped the first time round
+        self.ram_watch_hits.clear();
+
+        let undone = rewind.step - target;
+        rewind.truncate(target);
+        self.rewind = Some(rewind);
+        undone
+    }
+
+    fn replay_input(&mut self, input: Input) {
+        match input {
+            Input::Keys(bits) => self.update_keys(bits_to_keys(bits)),
+            // the beeper already played this
+            Input::Tick => self.advance_timers(),
+        }
+    }
+
+    pub(crate) fn record_input(&mut self, input: Input) {
+        if let Some(rewind) = &mut self.rewind {
+            rewind.push_input(input);
+        }
+    }
+
+    // called by execute on either side of every instruction
+    pub(crate) fn begin_rewind_step(&mut self) {
+        let Some(rewind) = &self.rewind else { return };
+        let due = match rewind.snapshots.back() {
+            // right after turning on or a load there is nothing to replay from
+            None => true,
+            Some(newest) => newest.step != rewind.step && rewind.step % SNAPSHOT_INTERVAL == 0,
+        };
+        if !due {
+            return;
+        }
+        let state = compress(&self.save_state());
+        if let Some(rewind) = &mut self.rewind {
+            let snapshot = Snapshot { step: rewind.step, state };
+            rewind.size += snapshot_size(&snapshot);
+            rewind.snapshots.push_back(snapshot);
+            rewind.trim();
+        }
+    }
+    pub(crate) fn end_rewind_step(&mut self) {
+        if let Some(rewind) = &mut self.rewind {
+            rewind.step += 1;
+        }
+    }
+}
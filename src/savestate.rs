@@ -0,0 +1,320 @@
+use crate::components::{Resolution, STACK_SIZE};
+use crate::error::EmulatorError;
+use crate::keyhandler::KeyWait;
+use crate::quirks::{DisplayWait, MemoryIncrement, Platform, Quirks, SpriteEdges};
+use crate::random;
+use crate::Emulator;
+
+// save states are a fixed header followed by the machine, all integers little-endian:
+//
+//     magic "SCHIPSTA", version u16, hash of the ROM u64
+//     platform u8, quirks 8 x u8
+//     V0-VF 16 x u8, pc u16, i u16, delay u8, sound u8, waiting for vblank u8
+//     resolution u8, planes u8, audio pattern 16 x u8, pattern loaded u8, pitch u8
+//     held keys u16, last keys u16, key wait: stage u8 (idle, press, release), register u8, key u8
+//     call stack: depth u8, depth x u16
+//     rpl: count u8, count x u8
+//     ram: length u32, length x u8
+//     display: 2 planes x 128 columns x 64 rows, one bit a pixel
+//     random source: length u16, its description (see random.rs)
+//
+// bump STATE_VERSION whenever this changes, old files are refused rather than misread
+
+const STATE_MAGIC: &[u8; 8] = b"SCHIPSTA";
+pub const STATE_VERSION: u16 = 8;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], EmulatorError> {
+        if self.bytes.len() < n {
+            return Err(corrupt("the file ends early"));
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, EmulatorError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, EmulatorError> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+    fn u32(&mut self) -> Result<u32, EmulatorError> {
+        Ok(u32::from_le_bytes([self.u8()?, self.u8()?, self.u8()?, self.u8()?]))
+    }
+    fn u64(&mut self) -> Result<u64, EmulatorError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    fn bool(&mut self) -> Result<bool, EmulatorError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(corrupt("a flag is neither 0 nor 1")),
+        }
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], EmulatorError> {
+        let mut ret = [0u8; N];
+        ret.copy_from_slice(self.take(N)?);
+        Ok(ret)
+    }
+}
+
+fn corrupt(reason: &str) -> EmulatorError {
+    EmulatorError::CorruptState {
+        reason: reason.to_string(),
+    }
+}
+
+pub(crate) fn keys_to_bits(keys: &[bool; 0x10]) -> u16 {
+    keys.iter()
+        .enumerate()
+        .fold(0, |bits, (i, held)| bits | (u16::from(*held) << i))
+}
+
+pub(crate) fn bits_to_keys(bits: u16) -> [bool; 0x10] {
+    let mut keys = [false; 0x10];
+    for (i, key) in keys.iter_mut().enumerate() {
+        *key = bits & (1 << i) != 0;
+    }
+    keys
+}
+
+// the quirks one byte each, also how movies record them
+pub(crate) fn quirks_to_bytes(quirks: &Quirks) -> [u8; 8] {
+    [
+        u8::from(quirks.shift_uses_vy),
+        match quirks.memory_increment {
+            MemoryIncrement::Unchanged => 0,
+            MemoryIncrement::ByX => 1,
+            MemoryIncrement::ByXPlusOne => 2,
+        },
+        u8::from(quirks.jump_uses_vx),
+        u8::from(quirks.logic_resets_vf),
+        match quirks.sprite_edges {
+            SpriteEdges::Wrap => 0,
+            SpriteEdges::Clip => 1,
+        },
+        match quirks.display_wait {
+            DisplayWait::Never => 0,
+            DisplayWait::Always => 1,
+            DisplayWait::LowResolution => 2,
+        },
+        u8::from(quirks.key_release),
+        u8::from(quirks.flag_last),
+    ]
+}
+
+pub(crate) fn quirks_from_bytes(bytes: [u8; 8]) -> Result<Quirks, EmulatorError> {
+    let mut r = Reader { bytes: &bytes };
+    Ok(Quirks {
+        shift_uses_vy: r.bool()?,
+        memory_increment: match r.u8()? {
+            0 => MemoryIncrement::Unchanged,
+            1 => MemoryIncrement::ByX,
+            2 => MemoryIncrement::ByXPlusOne,
+            _ => return Err(corrupt("unknown memory increment quirk")),
+        },
+        jump_uses_vx: r.bool()?,
+        logic_resets_vf: r.bool()?,
+        sprite_edges: match r.u8()? {
+            0 => SpriteEdges::Wrap,
+            1 => SpriteEdges::Clip,
+            _ => return Err(corrupt("unknown sprite edges quirk")),
+        },
+        display_wait: match r.u8()? {
+            0 => DisplayWait::Never,
+            1 => DisplayWait::Always,
+            2 => DisplayWait::LowResolution,
+            _ => return Err(corrupt("unknown display wait quirk")),
+        },
+        key_release: r.bool()?,
+        flag_last: r.bool()?,
+    })
+}
+
+impl Emulator {
+    // the whole machine as a save state, see the layout above
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64 + self.ram.len() + 2048);
+        out.extend_from_slice(STATE_MAGIC);
+        out.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+
+        out.push(match self.platform {
+            Platform::Schip => 0,
+            Platform::XoChip => 1,
+        });
+        out.extend_from_slice(&quirks_to_bytes(&self.quirks));
+
+        out.extend(self.registers.iter().map(|r| r.value));
+        out.extend_from_slice(&self.pro_counter.to_le_bytes());
+        out.extend_from_slice(&self.mem_pointer.to_le_bytes());
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.push(u8::from(self.vblank_wait));
+
+        out.push(match self.resolution_mode {
+            Resolution::Low => 0,
+            Resolution::High => 1,
+        });
+        out.push(self.planes);
+        out.extend_from_slice(&self.audio_pattern);
+        out.push(u8::from(self.pattern_loaded));
+        out.push(self.pitch);
+
+        out.extend_from_slice(&keys_to_bits(&self.key_handler.keys).to_le_bytes());
+        out.extend_from_slice(&keys_to_bits(&self.key_handler.last_keys).to_le_bytes());
+        out.extend_from_slice(&match self.key_handler.wait {
+            KeyWait::Idle => [0, 0, 0],
+            KeyWait::Press(reg) => [1, reg, 0],
+            KeyWait::Release { reg, key } => [2, reg, key],
+        });
+
+        out.push(self.call_stack.len() as u8);
+        for addr in &self.call_stack {
+            out.extend_from_slice(&addr.to_le_bytes());
+        }
+        out.push(self.rpl_count);
+        out.extend_from_slice(self.rpl_flags());
+        out.extend_from_slice(&(self.ram.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.ram);
+
+        for plane in &self.display {
+            for column in plane {
+                for byte in column.chunks(8) {
+                    out.push(byte.iter().fold(0, |packed, pixel| packed << 1 | u8::from(*pixel)));
+                }
+            }
+        }
+        let rng = self.rng.describe();
+        out.extend_from_slice(&(rng.len() as u16).to_le_bytes());
+        out.extend_from_slice(rng.as_bytes());
+        out
+    }
+
+    // restores a save state, nothing changes unless the whole state reads back
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), EmulatorError> {
+        let mut r = Reader { bytes };
+        if r.take(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(corrupt("not a save state"));
+        }
+        let version = r.u16()?;
+        if version != STATE_VERSION {
+            return Err(EmulatorError::StateVersion { version });
+        }
+        // the RPL flags in it would be saved under the loaded ROM on exit
+        let rom_hash = r.u64()?;
+        if rom_hash != self.rom_hash {
+            return Err(EmulatorError::StateRom { hash: rom_hash });
+        }
+
+        let platform = match r.u8()? {
+            0 => Platform::Schip,
+            1 => Platform::XoChip,
+            _ => return Err(corrupt("unknown platform")),
+        };
+        let quirks = quirks_from_bytes(r.array()?)?;
+
+        let registers: [u8; 0x10] = r.array()?;
+        let pro_counter = r.u16()?;
+        let mem_pointer = r.u16()?;
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let vblank_wait = r.bool()?;
+
+        let resolution_mode = Resolution::from(r.bool()?);
+        let planes = r.u8()?;
+        if planes > 0b11 {
+            return Err(corrupt("unknown plane mask"));
+        }
+        let audio_pattern: [u8; 16] = r.array()?;
+        let pattern_loaded = r.bool()?;
+        let pitch = r.u8()?;
+
+        let keys = bits_to_keys(r.u16()?);
+        let last_keys = bits_to_keys(r.u16()?);
+        let wait = match r.array()? {
+            [0, 0, 0] => KeyWait::Idle,
+            [1, reg, 0] if reg < 0x10 => KeyWait::Press(reg),
+            [2, reg, key] if reg < 0x10 && key < 0x10 => KeyWait::Release { reg, key },
+            _ => return Err(corrupt("unknown key wait")),
+        };
+
+        let depth = r.u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(corrupt("the call stack is too deep"));
+        }
+        let mut call_stack = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            call_stack.push(r.u16()?);
+        }
+        let rpl_count = r.u8()?;
+        if rpl_count > 16 {
+            return Err(corrupt("there are more than 16 RPL flags"));
+        }
+        let mut rpl = [0u8; 16];
+        rpl[..rpl_count as usize].copy_from_slice(r.take(rpl_count as usize)?);
+        let ram_size = r.u32()? as usize;
+        if ram_size != platform.ram_size() {
+            return Err(corrupt("RAM is the wrong size for the platform"));
+        }
+        let ram = r.take(ram_size)?.to_vec();
+
+        let mut display = [[[false; 64]; 128]; 2];
+        for plane in display.iter_mut() {
+            for column in plane.iter_mut() {
+                let packed = r.take(8)?;
+                for (y, pixel) in column.iter_mut().enumerate() {
+                    *pixel = packed[y / 8] & (0x80 >> (y % 8)) != 0;
+                }
+            }
+        }
+        let rng_len = r.u16()? as usize;
+        let rng = std::str::from_utf8(r.take(rng_len)?)
+            .ok()
+            .and_then(|rng| random::parse_source(rng).ok())
+            .ok_or_else(|| corrupt("unknown random source"))?;
+        if !r.bytes.is_empty() {
+            return Err(corrupt("there is data after the end of the state"));
+        }
+
+        // everything read fine, now replace the machine
+        self.platform = platform;
+        self.quirks = quirks;
+        for (reg, value) in self.registers.iter_mut().zip(registers) {
+            reg.value = value;
+        }
+        self.pro_counter = pro_counter;
+        self.mem_pointer = mem_pointer;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.vblank_wait = vblank_wait;
+        self.resolution_mode = resolution_mode;
+        self.planes = planes;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        // the beeper may still be playing a pattern from before the load
+        self.pattern_loaded = pattern_loaded;
+        if pattern_loaded {
+            self.beeper.pattern(audio_pattern, pitch);
+        } else {
+            self.beeper.square();
+        }
+        self.key_handler.keys = keys;
+        self.key_handler.last_keys = last_keys;
+        self.key_handler.wait = wait;
+        self.call_stack = call_stack;
+        self.rpl = rpl;
+        self.rpl_count = rpl_count;
+        self.ram = ram;
+        self.display = display;
+        self.rng = rng;
+        // the history leads up to the old machine, not this one
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+        Ok(())
+    }
+}
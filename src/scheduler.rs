@@ -0,0 +1,48 @@
+// paces the machine in 60Hz ticks: each tick runs a budget of instructions and
+// decrements the timers once, whatever rate the host calls in at
+
+pub const TICK_RATE: u32 = 60;
+const TICK: f32 = 1.0 / TICK_RATE as f32;
+const MAX_BACKLOG: f32 = 0.25; // seconds of host time made up after a stall, the rest is dropped
+
+pub struct Scheduler {
+    ipf: u32,    // instructions per tick
+    speed: f32,  // emulated seconds per host second, above 1 is turbo and below is slow motion
+    backlog: f32, // emulated seconds not yet run, carried between calls
+}
+
+impl Scheduler {
+    pub fn new(ipf: u32) -> Scheduler {
+        Scheduler {
+            ipf: ipf.max(1),
+            speed: 1.0,
+            backlog: 0.0,
+        }
+    }
+    // the nearest whole budget per tick to an instructions-per-second rate
+    pub fn from_ips(ips: u32) -> Scheduler {
+        Scheduler::new((ips + TICK_RATE / 2) / TICK_RATE)
+    }
+
+    pub fn get_ipf(&self) -> u32 {
+        self.ipf
+    }
+    pub fn set_ipf(&mut self, ipf: u32) {
+        self.ipf = ipf.max(1);
+    }
+    pub fn get_speed(&self) -> f32 {
+        self.speed
+    }
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(1.0 / 16.0, 16.0);
+    }
+
+    // how many ticks are due after elapsed seconds of host time,
+    // whatever is left over waits for the next call
+    pub fn advance(&mut self, elapsed: f32) -> u32 {
+        self.backlog += elapsed.min(MAX_BACKLOG) * self.speed;
+        let ticks = (self.backlog / TICK) as u32;
+        self.backlog -= ticks as f32 * TICK;
+        ticks
+    }
+}
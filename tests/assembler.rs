@@ -0,0 +1,109 @@
+// disassembling a ROM and assembling what comes out gives the ROM back
+
+use schip_emu::assembler;
+use schip_emu::disasm::{disassemble, Disassembly, Syntax};
+use schip_emu::instruction::{Instruction, Value};
+use std::collections::BTreeSet;
+
+// one opcode of every instruction, reached in a line from 0x200
+const EVERY_INSTRUCTION: [u16; 58] = [
+    0x2206, // call 0x206
+    0x1208, // jump past the subroutine
+    0x0000, // operand words and data sit between the code
+    0x00EE, // 0x206: return
+    0x00E0, // 0x208
+    0x00FB, 0x00FC, 0x00FE, 0x00FF, 0x00C3, 0x00D4,
+    0x3A5C, 0x4A5C, 0x5AB0, 0x9AB0,
+    0x5AB2, 0x5AB3,
+    0x6F12, 0x7312,
+    0x8AB0, 0x8AB1, 0x8AB2, 0x8AB3, 0x8AB4, 0x8AB5, 0x8AB6, 0x8AB7, 0x8ABE,
+    0xA272, // data at 0x272
+    0xC7F0,
+    0xD12F, 0xD120,
+    0xE19E, 0xE1A1,
+    0xF000, 0x0300,
+    0xF301, 0xF401, 0xFF01, 0xF002, // every plane nibble decodes
+    0xF107, 0xF10A, 0xF115, 0xF118, 0xF11E,
+    0xF129, 0xF130, 0xF133, 0xF13A,
+    0xF155, 0xF165, 0xF175, 0xF185,
+    0x126E, // 0x26A: jump over a word
+    0x0000,
+    0xB270, // 0x26E: jump0 0x270
+    0x1270, // 0x270: loop
+    0x1234, // 0x272: data that happens to look like a jump
+];
+
+// which instruction an opcode is, with a jump's address and a load's operands left out
+fn variant(inst: &Instruction) -> (std::mem::Discriminant<Instruction>, Option<bool>) {
+    let by_register = match inst {
+        Instruction::SkipIfEqual { comp, .. } | Instruction::SkipIfUnequal { comp, .. } => Some(comp),
+        Instruction::Load { value, .. } => Some(value),
+        _ => None,
+    };
+    (std::mem::discriminant(inst), by_register.map(|value| matches!(value, Value::Register(_))))
+}
+
+fn rom() -> Vec<u8> {
+    EVERY_INSTRUCTION.iter().flat_map(|opcode| opcode.to_be_bytes()).collect()
+}
+
+#[test]
+fn the_rom_has_every_instruction() {
+    let all: BTreeSet<_> = (0..=u16::MAX)
+        .map(Instruction::decode)
+        .filter(|inst| !matches!(inst, Instruction::Invalid(_)))
+        .map(|inst| format!("{:?}", variant(&inst)))
+        .collect();
+    let ours: BTreeSet<_> = EVERY_INSTRUCTION
+        .iter()
+        .map(|opcode| format!("{:?}", variant(&Instruction::decode(*opcode))))
+        .collect();
+    let missing: Vec<_> = all.difference(&ours).collect();
+    assert!(missing.is_empty(), "no opcode for {:?}", missing);
+
+    // and it's all traced as code, apart from the three data words and F000's address
+    let disassembly = Disassembly::new(&rom(), 0x200);
+    let code = (0x200..0x274).step_by(2).filter(|addr| disassembly.is_code(*addr)).count();
+    assert_eq!(code, EVERY_INSTRUCTION.len() - 4);
+}
+
+#[test]
+fn classic_disassembly_assembles_to_the_same_bytes() {
+    let rom = rom();
+    let source = disassemble(&rom, 0x200, Syntax::Classic);
+    let assembled = assembler::assemble(&source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+    assert_eq!(assembled, rom, "\n{}", source);
+}
+
+#[test]
+fn disassembly_at_another_origin_assembles_to_the_same_bytes() {
+    // every address moves, so the labels have to follow
+    let rom: Vec<u8> = EVERY_INSTRUCTION
+        .iter()
+        .map(|opcode| match opcode & 0xF000 {
+            0x1000 | 0x2000 | 0xA000 | 0xB000 => opcode + 0x100,
+            _ => *opcode,
+        })
+        .flat_map(|opcode| opcode.to_be_bytes())
+        .collect();
+    let source = disassemble(&rom, 0x300, Syntax::Classic);
+    let assembled = assembler::assemble(&source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+    assert_eq!(assembled, rom, "\n{}", source);
+}
+
+#[test]
+fn labels_inside_instructions_survive_the_round_trip() {
+    let rom: Vec<u8> = [
+        0xF000, 0x0202, // i := long at its own address word
+        0x6012, // 0x204
+        0x1205, // into the middle of the load, where 12 12 is a jump
+        0x1212, // 0x208
+    ]
+    .iter()
+    .flat_map(|opcode: &u16| opcode.to_be_bytes())
+    .collect();
+    let source = disassemble(&rom, 0x200, Syntax::Classic);
+    assert!(source.contains("data_202:") && source.contains("label_205:"), "\n{}", source);
+    let assembled = assembler::assemble(&source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+    assert_eq!(assembled, rom, "\n{}", source);
+}
@@ -0,0 +1,143 @@
+// boots third-party test ROMs on the core and compares the display with an image
+// taken from a reference interpreter, so a passing run means agreement with another
+// implementation rather than with this one's past output. every case is a line of
+// tests/conformance/manifest:
+//
+//     <name> <rom> <platform> <preset> <frames> [<addr>=<byte> ...]
+//
+// the ROM is tests/conformance/roms/<rom>, the expected display is
+// tests/conformance/golden/<name>.txt, and the bytes after the frame count are
+// written to RAM before the first instruction, for ROMs that read a setting from
+// there. a golden image is `#` for a lit pixel and `.` for an unlit one, 64x32 for a
+// low resolution screen or 128x64. this test never writes them.
+
+use schip_emu::quirks::{Platform, QuirkPreset};
+use schip_emu::{parse_address, Emulator, EmulatorConfig};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const IPF: u32 = 20; // instructions per 60Hz frame
+
+struct Case {
+    name: String,
+    rom: PathBuf,
+    platform: Platform,
+    preset: QuirkPreset,
+    frames: u32,
+    pokes: Vec<(u16, u8)>,
+}
+
+fn conformance_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance")
+}
+
+fn parse_case(line: &str) -> Result<Case, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [name, rom, platform, preset, frames, pokes @ ..] = fields.as_slice() else {
+        return Err(format!("expected `<name> <rom> <platform> <preset> <frames>`, got {:?}", line));
+    };
+    let pokes = pokes
+        .iter()
+        .map(|poke| {
+            let (addr, byte) = poke.split_once('=').ok_or_else(|| format!("bad poke {:?}", poke))?;
+            let byte = parse_address(byte).ok().and_then(|byte| u8::try_from(byte).ok());
+            Ok((parse_address(addr)?, byte.ok_or_else(|| format!("bad byte in {:?}", poke))?))
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Case {
+        name: name.to_string(),
+        rom: conformance_dir().join("roms").join(rom),
+        platform: platform.parse()?,
+        preset: preset.parse()?,
+        frames: frames.parse().map_err(|_| format!("bad frame count {:?}", frames))?,
+        pokes,
+    })
+}
+
+fn cases() -> Vec<Case> {
+    let manifest = conformance_dir().join("manifest");
+    let source = fs::read_to_string(&manifest).unwrap_or_else(|e| panic!("could not read {:?}: {}", manifest, e));
+    source
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(number, line)| parse_case(line).unwrap_or_else(|e| panic!("manifest line {}: {}", number + 1, e)))
+        .collect()
+}
+
+// the frontend's loop: a frame's instructions until a wait, then the timers
+fn run(case: &Case) -> Result<Emulator, String> {
+    let mut emulator = Emulator::with_config(EmulatorConfig {
+        rom: case.rom.clone(),
+        load_address: 0x200,
+        font: None,
+        platform: case.platform,
+        quirks: case.preset.into(),
+        rpl_flags: None,
+    })
+    .map_err(|e| e.to_string())?;
+    for (addr, byte) in &case.pokes {
+        emulator.set_ram(*addr, *byte).map_err(|e| e.to_string())?;
+    }
+    for frame in 0..case.frames {
+        for _ in 0..IPF {
+            if emulator.waiting_for_key() || emulator.waiting_for_vblank() {
+                break;
+            }
+            emulator.execute().map_err(|e| format!("{} in frame {}", e, frame))?;
+        }
+        emulator.tick_timers();
+    }
+    Ok(emulator)
+}
+
+// the display at the golden image's size, a low resolution pixel is 2x2 on the core
+fn render(emulator: &Emulator, width: usize) -> String {
+    let scale = 128 / width;
+    let display = emulator.display();
+    let mut out = String::new();
+    for y in 0..64 / scale {
+        out.extend((0..width).map(|x| if display[x * scale][y * scale] { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+#[ignore = "no third-party test ROMs or reference images are vendored yet, see tests/conformance/manifest"]
+fn test_roms_match_a_reference_interpreter() {
+    let cases = cases();
+    // a manifest with nothing in it would pass without checking anything
+    assert!(!cases.is_empty(), "tests/conformance/manifest lists no test ROMs");
+    let mut failed = Vec::new();
+    for case in cases {
+        let golden = conformance_dir().join("golden").join(format!("{}.txt", case.name));
+        let expected = match fs::read_to_string(&golden) {
+            Ok(expected) => expected,
+            Err(e) => {
+                eprintln!("{}: could not read {:?}: {}", case.name, golden, e);
+                failed.push(case.name);
+                continue;
+            }
+        };
+        let width = expected.lines().next().map_or(0, |row| row.len());
+        if width != 64 && width != 128 {
+            eprintln!("{}: {:?} is neither 64 nor 128 pixels wide", case.name, golden);
+            failed.push(case.name);
+            continue;
+        }
+        match run(&case) {
+            Ok(emulator) if render(&emulator, width) == expected => (),
+            Ok(emulator) => {
+                eprintln!("{} doesn't match {:?}, the display was:\n{}", case.name, golden, render(&emulator, width));
+                failed.push(case.name);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", case.name, e);
+                failed.push(case.name);
+            }
+        }
+    }
+    assert!(failed.is_empty(), "display differs from the reference: {}", failed.join(", "));
+}
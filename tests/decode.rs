@@ -0,0 +1,128 @@
+// every one of the 65536 opcodes against a table of the instruction set written
+// out independently of Instruction::decode
+
+use schip_emu::instruction::{Instruction, Value};
+
+// an opcode belongs to an entry when opcode & mask == bits
+struct Pattern {
+    mask: u16,
+    bits: u16,
+    is: fn(&Instruction) -> bool,
+}
+
+fn patterns() -> Vec<Pattern> {
+    use Instruction::*;
+    let pattern = |mask, bits, is| Pattern { mask, bits, is };
+    let mut ret = vec![
+        pattern(0xFFFF, 0x00E0, |i| matches!(i, ClearScreen)),
+        pattern(0xFFFF, 0x00EE, |i| matches!(i, Return)),
+        pattern(0xFFFF, 0x00FB, |i| matches!(i, ScrollRight)),
+        pattern(0xFFFF, 0x00FC, |i| matches!(i, ScrollLeft)),
+        pattern(0xFFFF, 0x00FE, |i| matches!(i, HighResolution(false))),
+        pattern(0xFFFF, 0x00FF, |i| matches!(i, HighResolution(true))),
+        pattern(0xFFF0, 0x00C0, |i| matches!(i, ScrollDown(_))),
+        pattern(0xFFF0, 0x00D0, |i| matches!(i, ScrollUp(_))),
+        pattern(0xF000, 0x1000, |i| matches!(i, Jump(_))),
+        pattern(0xF000, 0x2000, |i| matches!(i, Call(_))),
+        pattern(0xF000, 0x3000, |i| matches!(i, SkipIfEqual { comp: Value::Byte(_), .. })),
+        pattern(0xF000, 0x4000, |i| matches!(i, SkipIfUnequal { comp: Value::Byte(_), .. })),
+        pattern(0xF00F, 0x5000, |i| matches!(i, SkipIfEqual { comp: Value::Register(_), .. })),
+        pattern(0xF00F, 0x5002, |i| matches!(i, SaveRange { .. })),
+        pattern(0xF00F, 0x5003, |i| matches!(i, LoadRange { .. })),
+        pattern(0xF000, 0x6000, |i| matches!(i, Load { value: Value::Byte(_), .. })),
+        pattern(0xF000, 0x7000, |i| matches!(i, AddInPlace { .. })),
+        pattern(0xF00F, 0x8000, |i| matches!(i, Load { value: Value::Register(_), .. })),
+        pattern(0xF00F, 0x8001, |i| matches!(i, Or { .. })),
+        pattern(0xF00F, 0x8002, |i| matches!(i, And { .. })),
+        pattern(0xF00F, 0x8003, |i| matches!(i, Xor { .. })),
+        pattern(0xF00F, 0x8004, |i| matches!(i, Add { .. })),
+        pattern(0xF00F, 0x8005, |i| matches!(i, Sub { .. })),
+        pattern(0xF00F, 0x8006, |i| matches!(i, ShiftRight { .. })),
+        pattern(0xF00F, 0x8007, |i| matches!(i, SubReverse { .. })),
+        pattern(0xF00F, 0x800E, |i| matches!(i, ShiftLeft { .. })),
+        pattern(0xF00F, 0x9000, |i| matches!(i, SkipIfUnequal { comp: Value::Register(_), .. })),
+        pattern(0xF000, 0xA000, |i| matches!(i, SetPointer(_))),
+        pattern(0xF000, 0xB000, |i| matches!(i, JumpPlus { .. })),
+        pattern(0xF000, 0xC000, |i| matches!(i, Random { .. })),
+        pattern(0xF00F, 0xD000, |i| matches!(i, DrawLarge { .. })),
+        pattern(0xF0FF, 0xE09E, |i| matches!(i, SkipIfKey(_))),
+        pattern(0xF0FF, 0xE0A1, |i| matches!(i, SkipIfNotKey(_))),
+        pattern(0xFFFF, 0xF000, |i| matches!(i, LongPointer)),
+        pattern(0xF0FF, 0xF001, |i| matches!(i, SelectPlanes(_))),
+        pattern(0xFFFF, 0xF002, |i| matches!(i, LoadAudio)),
+        pattern(0xF0FF, 0xF007, |i| matches!(i, GetTimer(_))),
+        pattern(0xF0FF, 0xF00A, |i| matches!(i, KeyBlock(_))),
+        pattern(0xF0FF, 0xF015, |i| matches!(i, SetTimer(_))),
+        pattern(0xF0FF, 0xF018, |i| matches!(i, SetSound(_))),
+        pattern(0xF0FF, 0xF01E, |i| matches!(i, AddPointer(_))),
+        pattern(0xF0FF, 0xF029, |i| matches!(i, GetDigit(_))),
+        pattern(0xF0FF, 0xF030, |i| matches!(i, GetLargeDigit(_))),
+        pattern(0xF0FF, 0xF033, |i| matches!(i, StoreDecimal(_))),
+        pattern(0xF0FF, 0xF03A, |i| matches!(i, SetPitch(_))),
+        pattern(0xF0FF, 0xF055, |i| matches!(i, StoreRegisters(_))),
+        pattern(0xF0FF, 0xF065, |i| matches!(i, LoadRegisters(_))),
+        pattern(0xF0FF, 0xF075, |i| matches!(i, StoreRegistersRPL(_))),
+        pattern(0xF0FF, 0xF085, |i| matches!(i, LoadRegistersRPL(_))),
+    ];
+    // DXY0 is the large sprite, every other height is an ordinary one
+    for n in 0x1..=0xF {
+        ret.push(pattern(0xF00F, 0xD000 | n, |i| matches!(i, Draw { .. })));
+    }
+    ret
+}
+
+#[test]
+fn every_opcode_decodes_to_its_one_instruction_or_invalid() {
+    let patterns = patterns();
+    for opcode in 0..=u16::MAX {
+        let inst = Instruction::decode(opcode);
+        let matching: Vec<&Pattern> = patterns.iter().filter(|p| opcode & p.mask == p.bits).collect();
+        match matching.as_slice() {
+            [] => assert_eq!(inst, Instruction::Invalid(opcode), "{:04X}", opcode),
+            [pattern] => assert!((pattern.is)(&inst), "{:04X} decoded to {:?}", opcode, inst),
+            _ => panic!("{:04X} is in {} entries of the table", opcode, matching.len()),
+        }
+    }
+}
+
+#[test]
+fn every_opcode_encodes_back_to_itself() {
+    for opcode in 0..=u16::MAX {
+        let inst = Instruction::decode(opcode);
+        assert_eq!(inst.encode(), opcode, "{:04X} decoded to {:?}", opcode, inst);
+    }
+}
+
+#[test]
+fn operands_come_from_the_right_nibbles() {
+    use Instruction::*;
+    assert_eq!(Instruction::decode(0x1ABC), Jump(0xABC));
+    assert_eq!(Instruction::decode(0x2ABC), Call(0xABC));
+    assert_eq!(Instruction::decode(0x3A5C), SkipIfEqual { reg: 0xA, comp: Value::Byte(0x5C) });
+    assert_eq!(Instruction::decode(0x5AB0), SkipIfEqual { reg: 0xA, comp: Value::Register(0xB) });
+    assert_eq!(Instruction::decode(0x6F12), Load { reg: 0xF, value: Value::Byte(0x12) });
+    assert_eq!(Instruction::decode(0x8AB0), Load { reg: 0xA, value: Value::Register(0xB) });
+    assert_eq!(Instruction::decode(0x7312), AddInPlace { reg: 0x3, byte: 0x12 });
+    assert_eq!(Instruction::decode(0x8AB4), Add { x: 0xA, y: 0xB });
+    assert_eq!(Instruction::decode(0x8ABE), ShiftLeft { x: 0xA, y: 0xB });
+    assert_eq!(Instruction::decode(0xB3AB), JumpPlus { addr: 0x3AB, x: 0x3 });
+    assert_eq!(Instruction::decode(0xC7F0), Random { x: 0x7, byte: 0xF0 });
+    assert_eq!(Instruction::decode(0xD12F), Draw { x: 0x1, y: 0x2, byte_count: 0xF });
+    assert_eq!(Instruction::decode(0xD120), DrawLarge { x: 0x1, y: 0x2 });
+    assert_eq!(Instruction::decode(0x00C7), ScrollDown(0x7));
+    assert_eq!(Instruction::decode(0xF301), SelectPlanes(0x3));
+    assert_eq!(Instruction::decode(0x5A32), SaveRange { x: 0xA, y: 0x3 });
+    assert_eq!(Instruction::decode(0xF965), LoadRegisters(0x9));
+}
+
+#[test]
+fn only_the_xo_chip_additions_are_xo_chip() {
+    for opcode in 0..=u16::MAX {
+        let inst = Instruction::decode(opcode);
+        let xo_chip = matches!(opcode & 0xFFF0, 0x00D0)
+            || matches!(opcode & 0xF00F, 0x5002 | 0x5003)
+            || matches!(opcode, 0xF000 | 0xF002)
+            || matches!(opcode & 0xF0FF, 0xF001 | 0xF03A);
+        assert_eq!(inst.is_xo_chip(), xo_chip, "{:04X} decoded to {:?}", opcode, inst);
+    }
+}
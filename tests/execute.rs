@@ -0,0 +1,724 @@
+// every arm of Emulator::execute, one instruction at a time from 0x200
+
+use schip_emu::audio::Beeper;
+use schip_emu::error::EmulatorError;
+use schip_emu::font;
+use schip_emu::instruction::Instruction;
+use schip_emu::quirks::{Platform, QuirkPreset, Quirks};
+use schip_emu::random::{CosmacVip, FixedSequence};
+use schip_emu::{Emulator, EmulatorConfig};
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn machine(platform: Platform, preset: QuirkPreset, program: &[u16]) -> Emulator {
+    machine_with(platform, preset.into(), program)
+}
+// for quirks no preset has
+fn machine_with(platform: Platform, quirks: Quirks, program: &[u16]) -> Emulator {
+    let mut emulator = Emulator::new(platform, quirks);
+    for (i, opcode) in program.iter().enumerate() {
+        let [msb, lsb] = opcode.to_be_bytes();
+        emulator.set_ram(0x200 + i as u16 * 2, msb).unwrap();
+        emulator.set_ram(0x201 + i as u16 * 2, lsb).unwrap();
+    }
+    emulator
+}
+fn schip(program: &[u16]) -> Emulator {
+    machine(Platform::Schip, QuirkPreset::Schip11, program)
+}
+fn xo_chip(program: &[u16]) -> Emulator {
+    machine(Platform::XoChip, QuirkPreset::XoChip, program)
+}
+
+fn step(emulator: &mut Emulator) {
+    emulator.execute().unwrap();
+}
+fn steps(emulator: &mut Emulator, count: usize) {
+    for _ in 0..count {
+        step(emulator);
+    }
+}
+
+fn lit(emulator: &Emulator) -> usize {
+    emulator.display().iter().flatten().filter(|pixel| **pixel).count()
+}
+
+// X, Y and VF after one 8XYN with VX = a and VY = b
+fn arithmetic(preset: QuirkPreset, opcode: u16, a: u8, b: u8) -> (u8, u8, u8) {
+    let mut emulator = machine(Platform::Schip, preset, &[opcode]);
+    let (x, y) = ((opcode >> 8 & 0xF) as u8, (opcode >> 4 & 0xF) as u8);
+    emulator.set_register(y, b);
+    emulator.set_register(x, a);
+    step(&mut emulator);
+    (emulator.get_register(x), emulator.get_register(y), emulator.get_register(0xF))
+}
+
+#[test]
+fn clear_screen() {
+    let mut emulator = schip(&[0xD005, 0x00E0]);
+    step(&mut emulator);
+    assert!(lit(&emulator) > 0);
+    assert!(emulator.execute().unwrap(), "00E0 redraws");
+    assert_eq!(lit(&emulator), 0);
+}
+
+#[test]
+fn jump() {
+    let mut emulator = schip(&[0x1ABC]);
+    step(&mut emulator);
+    assert_eq!(emulator.get_pro_counter(), 0xABC);
+}
+
+#[test]
+fn jump_plus_adds_vx_or_v0_by_quirk() {
+    let mut emulator = schip(&[0xB3AB]);
+    emulator.set_register(0x0, 0x01);
+    emulator.set_register(0x3, 0x05);
+    step(&mut emulator);
+    assert_eq!(emulator.get_pro_counter(), 0x3B0);
+
+    let mut emulator = machine(Platform::Schip, QuirkPreset::Chip8, &[0xB3AB]);
+    emulator.set_register(0x0, 0x01);
+    emulator.set_register(0x3, 0x05);
+    step(&mut emulator);
+    assert_eq!(emulator.get_pro_counter(), 0x3AC);
+}
+
+#[test]
+fn call_and_return() {
+    let mut emulator = schip(&[0x2400]);
+    emulator.set_ram(0x400, 0x00).unwrap();
+    emulator.set_ram(0x401, 0xEE).unwrap();
+    step(&mut emulator);
+    assert_eq!(emulator.get_pro_counter(), 0x400);
+    assert_eq!(emulator.get_call_stack(), &[0x202]);
+    step(&mut emulator);
+    assert_eq!(emulator.get_pro_counter(), 0x202);
+    assert!(emulator.get_call_stack().is_empty());
+}
+
+#[test]
+fn return_with_nothing_called_underflows() {
+    let mut emulator = schip(&[0x00EE]);
+    assert!(matches!(emulator.execute(), Err(EmulatorError::StackUnderflow { pc: 0x200 })));
+}
+
+#[test]
+fn calling_forever_overflows() {
+    let mut emulator = schip(&[0x2200]);
+    let result = (0..100).find_map(|_| emulator.execute().err());
+    assert!(matches!(result, Some(EmulatorError::StackOverflow { pc: 0x200 })));
+}
+
+#[test]
+fn skips() {
+    // opcode, VX, VY (V2), whether it skips
+    let cases = [
+        (0x3142, 0x42, 0x00, true),
+        (0x3142, 0x41, 0x00, false),
+        (0x4142, 0x42, 0x00, false),
+        (0x4142, 0x41, 0x00, true),
+        (0x5120, 0x42, 0x42, true),
+        (0x5120, 0x42, 0x43, false),
+        (0x9120, 0x42, 0x42, false),
+        (0x9120, 0x42, 0x43, true),
+    ];
+    for (opcode, x, y, skips) in cases {
+        let mut emulator = schip(&[opcode]);
+        emulator.set_register(0x1, x);
+        emulator.set_register(0x2, y);
+        step(&mut emulator);
+        let pc = if skips { 0x204 } else { 0x202 };
+        assert_eq!(emulator.get_pro_counter(), pc, "{:04X} with {:02X}, {:02X}", opcode, x, y);
+    }
+}
+
+#[test]
+fn xo_chip_skips_step_over_long_pointers() {
+    let mut emulator = xo_chip(&[0x3000, 0xF000, 0x1234]);
+    step(&mut emulator);
+    assert_eq!(emulator.get_pro_counter(), 0x206);
+}
+
+#[test]
+fn skip_if_key() {
+    let mut keys = [false; 0x10];
+    keys[0xA] = true;
+    for (opcode, held, skips) in [(0xE19E, 0xA, true), (0xE19E, 0xB, false), (0xE1A1, 0xA, false), (0xE1A1, 0xB, true)] {
+        let mut emulator = schip(&[opcode]);
+        emulator.update_keys(keys);
+        emulator.set_register(0x1, held);
+        step(&mut emulator);
+        let pc = if skips { 0x204 } else { 0x202 };
+        assert_eq!(emulator.get_pro_counter(), pc, "{:04X} with key {:X}", opcode, held);
+    }
+}
+
+#[test]
+fn key_block_waits_for_a_press() {
+    let mut keys = [false; 0x10];
+    let mut emulator = schip(&[0xF30A]);
+    step(&mut emulator);
+    assert!(emulator.waiting_for_key());
+    emulator.update_keys(keys);
+    assert!(emulator.waiting_for_key());
+    keys[0x7] = true;
+    emulator.update_keys(keys);
+    assert!(!emulator.waiting_for_key());
+    assert_eq!(emulator.get_register(0x3), 0x7);
+}
+
+#[test]
+fn key_block_waits_for_the_release_by_quirk() {
+    let mut keys = [false; 0x10];
+    let mut emulator = machine(Platform::Schip, QuirkPreset::Chip8, &[0xF30A]);
+    step(&mut emulator);
+    keys[0x7] = true;
+    emulator.update_keys(keys);
+    assert!(emulator.waiting_for_key());
+    keys[0x7] = false;
+    emulator.update_keys(keys);
+    assert!(!emulator.waiting_for_key());
+    assert_eq!(emulator.get_register(0x3), 0x7);
+}
+
+#[test]
+fn load() {
+    let mut emulator = schip(&[0x6A42, 0x8BA0]);
+    steps(&mut emulator, 2);
+    assert_eq!(emulator.get_register(0xA), 0x42);
+    assert_eq!(emulator.get_register(0xB), 0x42);
+}
+
+#[test]
+fn add_in_place_wraps_and_leaves_vf() {
+    let mut emulator = schip(&[0x7102]);
+    emulator.set_register(0x1, 0xFF);
+    emulator.set_register(0xF, 0x42);
+    step(&mut emulator);
+    assert_eq!(emulator.get_register(0x1), 0x01);
+    assert_eq!(emulator.get_register(0xF), 0x42);
+}
+
+#[test]
+fn logic() {
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8121, 0x0F, 0x3C).0, 0x3F);
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8122, 0x0F, 0x3C).0, 0x0C);
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8123, 0x0F, 0x3C).0, 0x33);
+}
+
+#[test]
+fn logic_resets_vf_by_quirk() {
+    for opcode in [0x8121, 0x8122, 0x8123] {
+        let mut emulator = machine(Platform::Schip, QuirkPreset::Chip8, &[opcode]);
+        emulator.set_register(0xF, 0x42);
+        step(&mut emulator);
+        assert_eq!(emulator.get_register(0xF), 0x00, "{:04X}", opcode);
+
+        let mut emulator = schip(&[opcode]);
+        emulator.set_register(0xF, 0x42);
+        step(&mut emulator);
+        assert_eq!(emulator.get_register(0xF), 0x42, "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn add_sets_vf_on_carry() {
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8124, 0x10, 0x20), (0x30, 0x20, 0));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8124, 0xF0, 0x20), (0x10, 0x20, 1));
+}
+
+#[test]
+fn sub_sets_vf_without_borrow() {
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8125, 0x30, 0x10), (0x20, 0x10, 1));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8125, 0x10, 0x10), (0x00, 0x10, 1));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8125, 0x10, 0x30), (0xE0, 0x30, 0));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8127, 0x10, 0x30), (0x20, 0x30, 1));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8127, 0x30, 0x10), (0xE0, 0x10, 0));
+}
+
+#[test]
+fn shifts_set_vf_to_the_bit_shifted_out() {
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8126, 0x05, 0x00), (0x02, 0x00, 1));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x8126, 0x04, 0x00), (0x02, 0x00, 0));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x812E, 0x81, 0x00), (0x02, 0x00, 1));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x812E, 0x41, 0x00), (0x82, 0x00, 0));
+}
+
+#[test]
+fn shifts_read_vy_by_quirk() {
+    assert_eq!(arithmetic(QuirkPreset::Chip8, 0x8126, 0xFF, 0x04), (0x02, 0x04, 0));
+    assert_eq!(arithmetic(QuirkPreset::Chip8, 0x812E, 0x00, 0x81), (0x02, 0x81, 1));
+}
+
+#[test]
+fn vf_as_an_operand_is_read_before_the_flag_is_written() {
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x81F4, 0x10, 0x20), (0x30, 0, 0));
+    assert_eq!(arithmetic(QuirkPreset::Schip11, 0x81F5, 0x10, 0x20), (0xF0, 0, 0));
+}
+
+#[test]
+fn vf_as_the_destination_keeps_the_flag() {
+    // every preset writes the flag last, so it's what VF ends up holding. the shifts get
+    // the same VX and VY, since chip8 shifts VY
+    use QuirkPreset::*;
+    for preset in [Chip8, Schip10, Schip11, Modern, XoChip] {
+        assert_eq!(arithmetic(preset, 0x8F14, 0xF0, 0x20).2, 1, "{}", preset);
+        assert_eq!(arithmetic(preset, 0x8F15, 0x30, 0x10).2, 1, "{}", preset);
+        assert_eq!(arithmetic(preset, 0x8F17, 0x10, 0x30).2, 1, "{}", preset);
+        assert_eq!(arithmetic(preset, 0x8F16, 0x05, 0x05).2, 1, "{}", preset);
+        assert_eq!(arithmetic(preset, 0x8F1E, 0x81, 0x81).2, 1, "{}", preset);
+    }
+}
+
+#[test]
+fn vf_as_the_destination_keeps_the_result_by_quirk() {
+    let quirks = Quirks {
+        flag_last: false,
+        ..QuirkPreset::Schip11.into()
+    };
+    for (opcode, a, b, result) in [(0x8F14, 0xF0, 0x20, 0x10), (0x8F15, 0x30, 0x10, 0x20), (0x8F16, 0x05, 0x00, 0x02)] {
+        let mut emulator = machine_with(Platform::Schip, quirks, &[opcode]);
+        emulator.set_register(0x1, b);
+        emulator.set_register(0xF, a);
+        step(&mut emulator);
+        assert_eq!(emulator.get_register(0xF), result, "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn pointer() {
+    let mut emulator = schip(&[0xA123, 0xF11E]);
+    emulator.set_register(0x1, 0x10);
+    steps(&mut emulator, 2);
+    assert_eq!(emulator.get_mem_pointer(), 0x133);
+}
+
+#[test]
+fn random_masks_the_source() {
+    let mut emulator = schip(&[0xC30F, 0xC3F0]);
+    emulator.set_rng(Box::new(FixedSequence::new(vec![0xAB, 0xCD])));
+    step(&mut emulator);
+    assert_eq!(emulator.get_register(0x3), 0x0B);
+    step(&mut emulator);
+    assert_eq!(emulator.get_register(0x3), 0xC0);
+}
+
+#[test]
+fn vip_random_reads_the_interpreter_page() {
+    // R9 counts up, the page byte at its low half plus its high half is the roll
+    let mut emulator = schip(&[0xC0FF, 0xC1FF, 0xC2FF]);
+    for i in 0..0x100 {
+        emulator.set_ram(0x100 + i, 0x10u8.wrapping_add(i as u8)).unwrap();
+    }
+    emulator.set_rng(Box::new(CosmacVip::new(0)));
+    steps(&mut emulator, 3);
+    assert_eq!([0x0, 0x1, 0x2].map(|x| emulator.get_register(x)), [0x11, 0x23, 0x36]);
+    assert_eq!(emulator.rng_description(), "vip:13827");
+
+    // with no interpreter there, the program's first page stands in
+    let mut emulator = schip(&[0xC0FF, 0xC1FF, 0xC2FF]);
+    emulator.set_rng(Box::new(CosmacVip::new(0)));
+    steps(&mut emulator, 3);
+    assert_eq!([0x0, 0x1, 0x2].map(|x| emulator.get_register(x)), [0xFF, 0xC0, 0xBF]);
+}
+
+#[test]
+fn draw_xors_and_flags_collisions() {
+    // the digit 0 from the font at (2, 3), twice
+    let mut emulator = schip(&[0xD125, 0xD125]);
+    emulator.set_register(0x1, 2);
+    emulator.set_register(0x2, 3);
+    assert!(emulator.execute().unwrap(), "DXYN redraws");
+    assert_eq!(emulator.get_register(0xF), 0);
+    // the top row of 0 is 0xF0, low resolution pixels are 2x2
+    assert!(emulator.display()[4][6] && emulator.display()[11][7]);
+    assert!(!emulator.display()[12][6]);
+    step(&mut emulator);
+    assert_eq!(emulator.get_register(0xF), 1);
+    assert_eq!(lit(&emulator), 0);
+}
+
+#[test]
+fn draw_waits_for_vblank_by_quirk() {
+    let mut emulator = machine(Platform::Schip, QuirkPreset::Chip8, &[0xD005]);
+    step(&mut emulator);
+    assert!(emulator.waiting_for_vblank());
+    emulator.tick_timers();
+    assert!(!emulator.waiting_for_vblank());
+
+    let mut emulator = machine(Platform::Schip, QuirkPreset::Modern, &[0xD005]);
+    step(&mut emulator);
+    assert!(!emulator.waiting_for_vblank());
+
+    // the HP48 only waited in low resolution
+    let mut emulator = schip(&[0xD005, 0x00FF, 0xD005]);
+    step(&mut emulator);
+    assert!(emulator.waiting_for_vblank());
+    emulator.tick_timers();
+    steps(&mut emulator, 2);
+    assert!(!emulator.waiting_for_vblank());
+}
+
+#[test]
+fn high_resolution() {
+    let mut emulator = schip(&[0x00FF, 0xD125, 0x00FE]);
+    emulator.set_register(0x1, 100);
+    emulator.set_register(0x2, 50);
+    steps(&mut emulator, 2);
+    assert!(emulator.display()[100][50] && !emulator.display()[101][51]);
+    step(&mut emulator);
+    // back in low resolution the same position wraps to (36, 18), drawn at double size
+    emulator.set_pro_counter(0x202);
+    emulator.execute().unwrap();
+    assert!(emulator.display()[72][36] && emulator.display()[73][37]);
+}
+
+#[test]
+fn draw_large() {
+    // a 16x16 frame
+    let mut emulator = schip(&[0x00FF, 0xD120, 0xD120]);
+    emulator.set_mem_pointer(0x300);
+    for row in 0..16 {
+        let bits: u16 = if row == 0 || row == 15 { 0xFFFF } else { 0x8001 };
+        emulator.set_ram(0x300 + row * 2, (bits >> 8) as u8).unwrap();
+        emulator.set_ram(0x301 + row * 2, bits as u8).unwrap();
+    }
+    emulator.set_register(0x1, 20);
+    emulator.set_register(0x2, 10);
+    steps(&mut emulator, 2);
+    assert_eq!(emulator.get_register(0xF), 0);
+    assert_eq!(lit(&emulator), 60);
+    assert!(emulator.display()[20][10] && emulator.display()[35][25]);
+    assert!(!emulator.display()[21][11]);
+    step(&mut emulator);
+    assert_eq!(emulator.get_register(0xF), 1);
+    assert_eq!(lit(&emulator), 0);
+}
+
+#[test]
+fn sprites_clip_or_wrap_at_the_edges_by_quirk() {
+    // an 8x1 bar from x = 124 and a 16x16 frame from (120, 60), in high resolution
+    let program = [0x00FF, 0xA300, 0xD121, 0xA310, 0xD340];
+    let draw = |preset: QuirkPreset| {
+        let mut emulator = machine(Platform::XoChip, preset, &program);
+        emulator.set_ram(0x300, 0xFF).unwrap();
+        for row in 0..16 {
+            let bits: u16 = if row == 0 || row == 15 { 0xFFFF } else { 0x8001 };
+            emulator.set_ram(0x310 + row * 2, (bits >> 8) as u8).unwrap();
+            emulator.set_ram(0x311 + row * 2, bits as u8).unwrap();
+        }
+        emulator.set_register(0x1, 124);
+        emulator.set_register(0x2, 0);
+        emulator.set_register(0x3, 120);
+        emulator.set_register(0x4, 60);
+        steps(&mut emulator, 5);
+        emulator
+    };
+
+    let clipped = draw(QuirkPreset::Schip11);
+    assert!(clipped.display()[127][0] && !clipped.display()[0][0]);
+    assert!(clipped.display()[120][63] && !clipped.display()[120][0]);
+    assert!(!clipped.display()[7][60] && !clipped.display()[7][11]);
+    assert_eq!(lit(&clipped), 4 + 8 + 3);
+
+    let wrapped = draw(QuirkPreset::XoChip);
+    assert!(wrapped.display()[127][0] && wrapped.display()[0][0] && wrapped.display()[3][0]);
+    // the frame's right column comes back at x = 7, its bottom row at y = 11
+    assert!(wrapped.display()[7][60] && wrapped.display()[120][11] && wrapped.display()[7][11]);
+    assert_eq!(lit(&wrapped), 8 + 60);
+}
+
+#[test]
+fn sprites_start_on_screen_whatever_the_quirk() {
+    // drawn at (130, 70) in high resolution, which is (2, 6)
+    for preset in [QuirkPreset::Schip11, QuirkPreset::XoChip] {
+        let mut emulator = machine(Platform::XoChip, preset, &[0x00FF, 0xD121]);
+        emulator.set_mem_pointer(0x300);
+        emulator.set_ram(0x300, 0x80).unwrap();
+        emulator.set_register(0x1, 130);
+        emulator.set_register(0x2, 70);
+        steps(&mut emulator, 2);
+        assert!(emulator.display()[2][6], "{}", preset);
+    }
+}
+
+#[test]
+fn scrolls() {
+    // opcode, where the pixel drawn at (10, 10) in high resolution ends up
+    let cases = [(0x00FB, (14, 10)), (0x00FC, (6, 10)), (0x00C3, (10, 13))];
+    for (opcode, (x, y)) in cases {
+        let mut emulator = schip(&[0x00FF, 0xD121, opcode]);
+        emulator.set_mem_pointer(0x300);
+        emulator.set_ram(0x300, 0x80).unwrap();
+        emulator.set_register(0x1, 10);
+        emulator.set_register(0x2, 10);
+        steps(&mut emulator, 3);
+        assert!(emulator.display()[x][y], "{:04X}", opcode);
+        assert_eq!(lit(&emulator), 1, "{:04X}", opcode);
+    }
+
+    let mut emulator = xo_chip(&[0x00FF, 0xD121, 0x00D3]);
+    emulator.set_mem_pointer(0x300);
+    emulator.set_ram(0x300, 0x80).unwrap();
+    emulator.set_register(0x1, 10);
+    emulator.set_register(0x2, 10);
+    steps(&mut emulator, 3);
+    assert!(emulator.display()[10][7]);
+}
+
+#[test]
+fn select_planes() {
+    let mut emulator = xo_chip(&[0xF201, 0xD121, 0xF301, 0x00E0]);
+    emulator.set_mem_pointer(0x300);
+    emulator.set_ram(0x300, 0x80).unwrap();
+    steps(&mut emulator, 2);
+    assert_eq!(emulator.pixel(0, 0), 2);
+    assert!(!emulator.display()[0][0], "display() is the first plane");
+    steps(&mut emulator, 2);
+    assert_eq!(emulator.pixel(0, 0), 0);
+}
+
+#[test]
+fn timers() {
+    let mut emulator = schip(&[0xF115, 0xF218, 0xF307]);
+    emulator.set_register(0x1, 0x10);
+    emulator.set_register(0x2, 0x20);
+    steps(&mut emulator, 2);
+    assert_eq!(emulator.get_delay_timer(), 0x10);
+    assert_eq!(emulator.get_sound_timer(), 0x20);
+    emulator.tick_timers();
+    step(&mut emulator);
+    assert_eq!(emulator.get_register(0x3), 0x0F);
+}
+
+#[test]
+fn digits() {
+    let mut emulator = schip(&[0xF129, 0xF130]);
+    emulator.set_register(0x1, 0xA);
+    step(&mut emulator);
+    assert_eq!(emulator.get_mem_pointer(), font::SMALL_FONT_ADDR + 0xA * 5);
+    step(&mut emulator);
+    assert_eq!(emulator.get_mem_pointer(), font::LARGE_FONT_ADDR + 0xA * 10);
+}
+
+#[test]
+fn store_decimal() {
+    let mut emulator = schip(&[0xF133]);
+    emulator.set_register(0x1, 234);
+    emulator.set_mem_pointer(0x300);
+    step(&mut emulator);
+    assert_eq!(emulator.get_ram_slice(0x300, 0x303).unwrap(), &[2, 3, 4]);
+}
+
+#[test]
+fn store_and_load_registers_move_i_by_quirk() {
+    for (preset, increment) in [(QuirkPreset::Schip11, 0), (QuirkPreset::Schip10, 2), (QuirkPreset::Chip8, 3)] {
+        let mut emulator = machine(Platform::Schip, preset, &[0xF255, 0xF265]);
+        for reg in 0..3 {
+            emulator.set_register(reg, 0x10 + reg);
+        }
+        emulator.set_mem_pointer(0x300);
+        step(&mut emulator);
+        assert_eq!(emulator.get_ram_slice(0x300, 0x304).unwrap(), &[0x10, 0x11, 0x12, 0x00]);
+        assert_eq!(emulator.get_mem_pointer(), 0x300 + increment, "{}", preset);
+
+        emulator.set_mem_pointer(0x300);
+        for reg in 0..3 {
+            emulator.set_register(reg, 0);
+        }
+        step(&mut emulator);
+        assert_eq!((0..3).map(|reg| emulator.get_register(reg)).collect::<Vec<_>>(), [0x10, 0x11, 0x12]);
+        assert_eq!(emulator.get_mem_pointer(), 0x300 + increment, "{}", preset);
+    }
+}
+
+#[test]
+fn rpl_flags() {
+    let mut emulator = schip(&[0xF275, 0xF285]);
+    for reg in 0..3 {
+        emulator.set_register(reg, 0x10 + reg);
+    }
+    step(&mut emulator);
+    assert_eq!(&emulator.rpl_flags()[..4], &[0x10, 0x11, 0x12, 0x00]);
+    for reg in 0..3 {
+        emulator.set_register(reg, 0);
+    }
+    step(&mut emulator);
+    assert_eq!(emulator.get_register(0x2), 0x12);
+}
+
+#[test]
+fn rpl_flags_past_the_platform_are_out_of_bounds() {
+    let mut emulator = schip(&[0xF875]);
+    assert!(matches!(emulator.execute(), Err(EmulatorError::RplOutOfBounds { index: 8 })));
+}
+
+#[test]
+fn save_and_load_range() {
+    let mut emulator = xo_chip(&[0x5132, 0x5313]);
+    for reg in 1..4 {
+        emulator.set_register(reg, 0x10 + reg);
+    }
+    emulator.set_mem_pointer(0x300);
+    step(&mut emulator);
+    assert_eq!(emulator.get_ram_slice(0x300, 0x303).unwrap(), &[0x11, 0x12, 0x13]);
+    assert_eq!(emulator.get_mem_pointer(), 0x300);
+    // backwards, V3 from the first byte
+    step(&mut emulator);
+    assert_eq!(emulator.get_register(0x3), 0x11);
+    assert_eq!(emulator.get_register(0x1), 0x13);
+}
+
+#[test]
+fn long_pointer() {
+    let mut emulator = xo_chip(&[0xF000, 0x1234]);
+    step(&mut emulator);
+    assert_eq!(emulator.get_mem_pointer(), 0x1234);
+    assert_eq!(emulator.get_pro_counter(), 0x204);
+}
+
+// the last pattern and pitch a beeper was given
+type Heard = Rc<Cell<Option<([u8; 16], u8)>>>;
+struct PatternBeeper(Heard);
+impl Beeper for PatternBeeper {
+    fn tick(&mut self, _on: bool) {}
+    fn pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        self.0.set(Some((pattern, pitch)));
+    }
+    fn square(&mut self) {
+        self.0.set(None);
+    }
+}
+
+#[test]
+fn audio_pattern_and_pitch() {
+    let heard: Heard = Rc::new(Cell::new(None));
+    let mut emulator = xo_chip(&[0xF002, 0xF13A]);
+    emulator.set_beeper(Box::new(PatternBeeper(heard.clone())));
+    emulator.set_mem_pointer(0x300);
+    for i in 0..16 {
+        emulator.set_ram(0x300 + i, i as u8).unwrap();
+    }
+    let pattern: [u8; 16] = std::array::from_fn(|i| i as u8);
+    step(&mut emulator);
+    assert_eq!(heard.get(), Some((pattern, 64)));
+    emulator.set_register(0x1, 100);
+    step(&mut emulator);
+    assert_eq!(heard.get(), Some((pattern, 100)));
+}
+
+#[test]
+fn pitch_before_a_pattern_keeps_the_square_wave() {
+    let heard: Heard = Rc::new(Cell::new(None));
+    let mut emulator = xo_chip(&[0xF13A, 0xF002]);
+    emulator.set_beeper(Box::new(PatternBeeper(heard.clone())));
+    emulator.set_register(0x1, 100);
+    step(&mut emulator);
+    assert_eq!(heard.get(), None);
+    // and the pattern, once there is one, plays at that pitch
+    emulator.set_mem_pointer(0x300);
+    step(&mut emulator);
+    assert_eq!(heard.get(), Some(([0; 16], 100)));
+}
+
+#[test]
+fn loading_a_state_from_before_the_pattern_goes_back_to_the_square_wave() {
+    let heard: Heard = Rc::new(Cell::new(None));
+    let mut emulator = xo_chip(&[0xF002]);
+    emulator.set_beeper(Box::new(PatternBeeper(heard.clone())));
+    emulator.set_mem_pointer(0x300);
+    emulator.set_ram(0x300, 0xAA).unwrap();
+    let state = emulator.save_state();
+    step(&mut emulator);
+    assert!(heard.get().is_some());
+    emulator.load_state(&state).unwrap();
+    assert_eq!(heard.get(), None);
+}
+
+#[test]
+fn a_silent_pattern_comes_back_from_a_state() {
+    let heard: Heard = Rc::new(Cell::new(None));
+    let mut emulator = xo_chip(&[0xF002]);
+    emulator.set_beeper(Box::new(PatternBeeper(heard.clone())));
+    emulator.set_mem_pointer(0x300);
+    step(&mut emulator);
+    let state = emulator.save_state();
+    heard.set(None);
+    emulator.load_state(&state).unwrap();
+    assert_eq!(heard.get(), Some(([0; 16], 64)));
+}
+
+#[test]
+fn states_of_another_rom_are_refused() {
+    let state = xo_chip(&[0x00E0]).save_state();
+    let dir = std::env::temp_dir().join(format!("schip-emu-state-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("rom.ch8");
+    std::fs::write(&rom, [0x00, 0xE0]).unwrap();
+    let mut emulator = Emulator::with_config(EmulatorConfig {
+        rom,
+        load_address: 0x200,
+        font: None,
+        platform: Platform::XoChip,
+        quirks: QuirkPreset::XoChip.into(),
+        rpl_flags: None,
+    })
+    .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(emulator.load_state(&state), Err(EmulatorError::StateRom { .. })));
+    let own = emulator.save_state();
+    assert!(emulator.load_state(&own).is_ok());
+}
+
+#[test]
+fn reads_past_the_end_of_ram_are_errors() {
+    // a large sprite or audio pattern that would run off the top of 64K
+    let mut emulator = xo_chip(&[0xF000, 0xFFF0, 0x00FF, 0xD000]);
+    steps(&mut emulator, 2);
+    assert!(matches!(emulator.execute(), Err(EmulatorError::MemoryOutOfBounds { addr: 0x10000 })));
+
+    let mut emulator = xo_chip(&[0xF000, 0xFFDF, 0xF301, 0xD000]);
+    steps(&mut emulator, 2);
+    assert!(matches!(emulator.execute(), Err(EmulatorError::MemoryOutOfBounds { addr: 0x10000 })));
+
+    let mut emulator = xo_chip(&[0xF000, 0xFFF8, 0xF002]);
+    step(&mut emulator);
+    assert!(matches!(emulator.execute(), Err(EmulatorError::MemoryOutOfBounds { addr: 0x10000 })));
+
+    let mut emulator = schip(&[0xAFFC, 0xD005]);
+    step(&mut emulator);
+    assert!(matches!(emulator.execute(), Err(EmulatorError::MemoryOutOfBounds { addr: 0x1000 })));
+
+    // going through RAM from I stops at the end too, whether on 4K or 64K
+    for opcode in [0xF233, 0xF255, 0xF265, 0x5022, 0x5023] {
+        if !Instruction::decode(opcode).is_xo_chip() {
+            let mut emulator = schip(&[0xAFFE, opcode]);
+            step(&mut emulator);
+            let result = emulator.execute();
+            assert!(matches!(result, Err(EmulatorError::MemoryOutOfBounds { addr: 0x1000 })), "{:04X}", opcode);
+        }
+        let mut emulator = xo_chip(&[0xF000, 0xFFFE, opcode]);
+        step(&mut emulator);
+        let result = emulator.execute();
+        assert!(matches!(result, Err(EmulatorError::MemoryOutOfBounds { addr: 0x10000 })), "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn invalid_opcodes_are_errors() {
+    let mut emulator = schip(&[0x0000]);
+    assert!(matches!(emulator.execute(), Err(EmulatorError::InvalidOpcode { pc: 0x200, opcode: 0x0000 })));
+}
+
+#[test]
+fn xo_chip_opcodes_are_invalid_on_schip() {
+    for opcode in [0x00D1, 0xF101, 0x5012, 0x5013, 0xF000, 0xF002, 0xF03A] {
+        let mut emulator = schip(&[opcode]);
+        assert!(
+            matches!(emulator.execute(), Err(EmulatorError::InvalidOpcode { pc: 0x200, .. })),
+            "{:04X}",
+            opcode
+        );
+    }
+}
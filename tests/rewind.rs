@@ -0,0 +1,27 @@
+// stepping backward through the history the core keeps
+
+use schip_emu::quirks::{Platform, QuirkPreset};
+use schip_emu::Emulator;
+
+#[test]
+fn a_long_pause_keeps_the_history() {
+    // 7001 over and over, with the frontend handing over the same keys every frame
+    let mut emulator = Emulator::new(Platform::Schip, QuirkPreset::Schip11.into());
+    for addr in (0x200..0x300).step_by(2) {
+        emulator.set_ram(addr, 0x70).unwrap();
+        emulator.set_ram(addr + 1, 0x01).unwrap();
+    }
+    emulator.set_rewind_budget(64 * 1024);
+    let mut keys = [false; 0x10];
+    keys[0x5] = true;
+    emulator.update_keys(keys);
+    for _ in 0..10 {
+        emulator.execute().unwrap();
+    }
+    // an hour of a paused debugger at 60 frames a second
+    for _ in 0..60 * 60 * 60 {
+        emulator.update_keys(keys);
+    }
+    assert_eq!(emulator.step_back(4), 4);
+    assert_eq!(emulator.get_register(0x0), 6);
+}
@@ -0,0 +1,105 @@
+// assembles the ROMs written for this repo in tests/selftest, runs each for a fixed
+// number of instructions and compares the display with the image in
+// tests/selftest/golden. the images were written by this emulator and checked by eye,
+// so these catch regressions; agreement with other interpreters is conformance.rs's job.
+// run with UPDATE_GOLDEN=1 to write the images afresh.
+//
+// golden images are the 128x64 display, `#` for a lit pixel and `.` for an unlit one
+
+use schip_emu::assembler;
+use schip_emu::quirks::{Platform, QuirkPreset};
+use schip_emu::{Emulator, EmulatorConfig};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CYCLES: u32 = 2000;
+const IPF: u32 = 20; // instructions between timer ticks
+
+struct Case {
+    name: &'static str, // the golden image's name
+    rom: &'static str,
+    platform: Platform,
+    preset: QuirkPreset,
+}
+
+const CASES: [Case; 8] = [
+    Case { name: "opcodes", rom: "opcodes", platform: Platform::Schip, preset: QuirkPreset::Schip11 },
+    Case { name: "flags", rom: "flags", platform: Platform::Schip, preset: QuirkPreset::Schip11 },
+    Case { name: "quirks-chip8", rom: "quirks", platform: Platform::Schip, preset: QuirkPreset::Chip8 },
+    Case { name: "quirks-schip10", rom: "quirks", platform: Platform::Schip, preset: QuirkPreset::Schip10 },
+    Case { name: "quirks-schip11", rom: "quirks", platform: Platform::Schip, preset: QuirkPreset::Schip11 },
+    Case { name: "quirks-xochip", rom: "quirks", platform: Platform::XoChip, preset: QuirkPreset::XoChip },
+    Case { name: "scrolling-schip", rom: "scrolling", platform: Platform::Schip, preset: QuirkPreset::Schip11 },
+    Case { name: "scrolling-xochip", rom: "scrolling", platform: Platform::XoChip, preset: QuirkPreset::XoChip },
+];
+
+fn selftest_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("selftest")
+}
+
+// the ROM assembled from its source, written where the emulator can load it
+fn rom_path(rom: &str) -> PathBuf {
+    let source = selftest_dir().join(format!("{}.asm", rom));
+    let bytes = assembler::assemble_file(&source).unwrap_or_else(|e| panic!("{}", e));
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.ch8", rom));
+    fs::write(&out, bytes).unwrap();
+    out
+}
+
+fn run(case: &Case) -> Emulator {
+    let mut emulator = Emulator::with_config(EmulatorConfig {
+        rom: rom_path(case.rom),
+        load_address: 0x200,
+        font: None,
+        platform: case.platform,
+        quirks: case.preset.into(),
+        rpl_flags: None,
+    })
+    .unwrap();
+    for cycle in 0..CYCLES {
+        if let Err(e) = emulator.execute() {
+            panic!("{}: {} after {} instructions", case.name, e, cycle);
+        }
+        if cycle % IPF == IPF - 1 {
+            emulator.tick_timers();
+        }
+    }
+    emulator
+}
+
+fn render(emulator: &Emulator) -> String {
+    let display = emulator.display();
+    let mut out = String::new();
+    for y in 0..64 {
+        out.extend(display.iter().map(|column| if column[y] { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn self_test_roms_match_golden_images() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failed = Vec::new();
+    for case in &CASES {
+        let image = render(&run(case));
+        let golden = selftest_dir().join("golden").join(format!("{}.txt", case.name));
+        if update {
+            fs::write(&golden, &image).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == image => (),
+            Ok(_) => {
+                eprintln!("{} doesn't match {:?}, the display was:\n{}", case.name, golden, image);
+                failed.push(case.name);
+            }
+            Err(e) => {
+                eprintln!("could not read {:?}: {}, the display was:\n{}", golden, e, image);
+                failed.push(case.name);
+            }
+        }
+    }
+    assert!(failed.is_empty(), "display differs from the golden image: {}", failed.join(", "));
+}
@@ -0,0 +1,27 @@
+; routines shared by the self-test ROMs. VD, VE is where the next result goes,
+; the ROMs start it at 1, 1 and leave both alone
+
+; a tick when V0 = V1, a cross when it doesn't, ten to a row
+check:
+    LD I, pass
+    SE V0, V1
+    LD I, fail
+    DRW VD, VE, 5
+    ADD VD, 6
+    SE VD, 61
+    RET
+    LD VD, 1
+    ADD VE, 6
+    RET
+
+; the hex digit in V0, for results that differ by quirk
+show:
+    LD F, V0
+    DRW VD, VE, 5
+    ADD VD, 5
+    RET
+
+pass:
+    SPRITE "....#..." "...#...." "#.#....." ".#......" "........"
+fail:
+    SPRITE "#...#..." ".#.#...." "..#....." ".#.#...." "#...#..."
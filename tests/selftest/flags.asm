@@ -0,0 +1,151 @@
+; VF after the arithmetic that sets it, and the result beside it. each pair of
+; checks is the result and then VF:
+;
+;     8XY4 no carry, 8XY4 carry, 8XY5 no borrow, 8XY5 borrow, 8XY5 equal
+;     8XY7 no borrow, 8XY7 borrow, 8XY6 bit out, 8XY6 no bit, 8XYE bit out
+;     8XYE no bit, 8XY4 with VF as Y
+;
+; then VF when it's X as well, which keeps the flag rather than the result: 8XY4,
+; 8XY5, 8XY7, 8XY6, 8XYE. and last VF from DXYN: no collision, collision
+
+    LD VD, 1
+    LD VE, 1
+
+    LD V2, 0x10
+    LD V3, 0x20
+    ADD V2, V3
+    LD V5, 0x30
+    LD V6, 0
+    CALL result_and_flag
+
+    LD V2, 0xF0
+    LD V3, 0x20
+    ADD V2, V3
+    LD V5, 0x10
+    LD V6, 1
+    CALL result_and_flag
+
+    LD V2, 0x30
+    LD V3, 0x10
+    SUB V2, V3
+    LD V5, 0x20
+    LD V6, 1
+    CALL result_and_flag
+
+    LD V2, 0x10
+    LD V3, 0x30
+    SUB V2, V3
+    LD V5, 0xE0
+    LD V6, 0
+    CALL result_and_flag
+
+    LD V2, 0x10
+    LD V3, 0x10
+    SUB V2, V3
+    LD V5, 0x00
+    LD V6, 1
+    CALL result_and_flag
+
+    LD V2, 0x10
+    LD V3, 0x30
+    SUBN V2, V3
+    LD V5, 0x20
+    LD V6, 1
+    CALL result_and_flag
+
+    LD V2, 0x30
+    LD V3, 0x10
+    SUBN V2, V3
+    LD V5, 0xE0
+    LD V6, 0
+    CALL result_and_flag
+
+    ; shifting a register into itself reads the same whichever register the quirk picks
+    LD V2, 0x05
+    SHR V2, V2
+    LD V5, 0x02
+    LD V6, 1
+    CALL result_and_flag
+
+    LD V2, 0x04
+    SHR V2, V2
+    LD V5, 0x02
+    LD V6, 0
+    CALL result_and_flag
+
+    LD V2, 0x81
+    SHL V2, V2
+    LD V5, 0x02
+    LD V6, 1
+    CALL result_and_flag
+
+    LD V2, 0x41
+    SHL V2, V2
+    LD V5, 0x82
+    LD V6, 0
+    CALL result_and_flag
+
+    LD VF, 0x10
+    LD V2, 0x20
+    ADD V2, VF
+    LD V5, 0x30
+    LD V6, 0
+    CALL result_and_flag
+
+    LD VF, 0xF0
+    LD V3, 0x20
+    ADD VF, V3
+    CALL flag_is_1
+
+    LD VF, 0x30
+    LD V3, 0x10
+    SUB VF, V3
+    CALL flag_is_1
+
+    LD VF, 0x10
+    LD V3, 0x30
+    SUBN VF, V3
+    CALL flag_is_1
+
+    LD VF, 0x05
+    SHR VF, VF
+    CALL flag_is_1
+
+    LD VF, 0x81
+    SHL VF, VF
+    CALL flag_is_1
+
+    ; out of the way of the checks, in the bottom right corner
+    LD I, pass
+    LD V2, 56
+    LD V3, 26
+    DRW V2, V3, 5
+    LD V0, VF
+    LD V1, 0
+    CALL check
+    LD I, pass
+    DRW V2, V3, 5
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+done:
+    JP done
+
+flag_is_1:
+    LD V0, VF
+    LD V1, 1
+    JP check
+
+; V2 against V5, then the VF the instruction left against V6
+result_and_flag:
+    LD V4, VF
+    LD V0, V2
+    LD V1, V5
+    CALL check
+    LD V0, V4
+    LD V1, V6
+    CALL check
+    RET
+
+INCLUDE "common.asm"
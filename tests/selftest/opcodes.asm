@@ -0,0 +1,184 @@
+; one tick per check of the instructions every interpreter agrees on, a cross
+; marks the one that broke. checks go left to right, top to bottom:
+;
+;     3XNN taken, 3XNN not taken, 4XNN, 5XY0, 9XY0, 7XNN wraps, 7XNN leaves VF, 8XY0, 8XY1, 8XY2
+;     8XY3, 8XY4, 8XY5, 8XY7, 8XY6, 8XYE, FX1E, FX33 hundreds, FX33 tens, FX33 ones
+;     FX55/FX65, 2NNN/00EE, 1NNN, FX29, EX9E, EXA1
+
+    LD VD, 1
+    LD VE, 1
+
+    LD V2, 7
+    LD V0, 1
+    SE V2, 7
+    LD V0, 0
+    LD V1, 1
+    CALL check
+
+    LD V0, 0
+    SE V2, 8
+    LD V0, 1
+    CALL check
+
+    LD V0, 1
+    SNE V2, 8
+    LD V0, 0
+    CALL check
+
+    LD V3, 7
+    LD V0, 1
+    SE V2, V3
+    LD V0, 0
+    CALL check
+
+    LD V3, 8
+    LD V0, 1
+    SNE V2, V3
+    LD V0, 0
+    CALL check
+
+    LD V0, 0xFE
+    ADD V0, 3
+    LD V1, 1
+    CALL check
+
+    LD VF, 0x42
+    LD V0, 0xFF
+    ADD V0, 2
+    LD V0, VF
+    LD V1, 0x42
+    CALL check
+
+    LD V2, 0x33
+    LD V0, V2
+    LD V1, 0x33
+    CALL check
+
+    LD V0, 0x0F
+    LD V2, 0xF0
+    OR V0, V2
+    LD V1, 0xFF
+    CALL check
+
+    LD V0, 0x3C
+    LD V2, 0x0F
+    AND V0, V2
+    LD V1, 0x0C
+    CALL check
+
+    LD V0, 0x3C
+    XOR V0, V2
+    LD V1, 0x33
+    CALL check
+
+    LD V0, 0x12
+    LD V2, 0x34
+    ADD V0, V2
+    LD V1, 0x46
+    CALL check
+
+    LD V0, 0x34
+    LD V2, 0x12
+    SUB V0, V2
+    LD V1, 0x22
+    CALL check
+
+    LD V0, 0x12
+    LD V2, 0x34
+    SUBN V0, V2
+    LD V1, 0x22
+    CALL check
+
+    ; shifting a register into itself reads the same whichever register the quirk picks
+    LD V0, 0x08
+    SHR V0, V0
+    LD V1, 0x04
+    CALL check
+
+    LD V0, 0x21
+    SHL V0, V0
+    LD V1, 0x42
+    CALL check
+
+    LD I, data
+    LD V2, 2
+    ADD I, V2
+    LD V0, [I]
+    LD V1, 0x56
+    CALL check
+
+    LD I, scratch
+    LD V2, 234
+    LD B, V2
+    LD V2, [I]
+    LD V4, V1
+    LD V5, V2
+    LD V1, 2
+    CALL check
+    LD V0, V4
+    LD V1, 3
+    CALL check
+    LD V0, V5
+    LD V1, 4
+    CALL check
+
+    LD V0, 0x11
+    LD V1, 0x22
+    LD V2, 0x33
+    LD I, scratch
+    LD [I], V2
+    LD V0, 0
+    LD V2, 0
+    LD I, scratch
+    LD V2, [I]
+    LD V0, V2
+    LD V1, 0x33
+    CALL check
+
+    LD V0, 0
+    CALL set_v0
+    LD V1, 1
+    CALL check
+
+    LD V0, 1
+    JP jumped
+    LD V0, 0
+jumped:
+    CALL check
+
+    LD V2, 0xA
+    LD F, V2
+    LD V0, [I]
+    LD V1, 0xF0
+    CALL check
+
+    ; no keys are held
+    LD V1, 1
+    LD V2, 5
+    LD V0, 1
+    SKP V2
+    JP skp_done
+    LD V0, 0
+skp_done:
+    CALL check
+
+    LD V0, 0
+    SKNP V2
+    JP sknp_done
+    LD V0, 1
+sknp_done:
+    CALL check
+
+done:
+    JP done
+
+set_v0:
+    LD V0, 1
+    RET
+
+data:
+    DB 0x12, 0x34, 0x56, 0x78
+scratch:
+    DB 0, 0, 0
+
+INCLUDE "common.asm"
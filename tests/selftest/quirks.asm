@@ -0,0 +1,71 @@
+; what the quirk profile does, as digits from left to right:
+;
+;     8XY6 result: 2 shifts VY, 0 shifts VX
+;     FX55/FX65 moves I by: 0, X or X + 1 (here X is 2)
+;     BXNN adds: 0 for V0, 1 for VX
+;     VF after 8XY1: 0 resets it, 5 leaves it
+;
+; then a bar drawn across the right edge and one across the bottom, which wrap
+; around to the other side or are clipped
+
+    JP start
+
+; BXNN lands here with V0 = 0 and V2 = 2, so the opcode's X is 2 and this has to
+; stay below 0x300
+jump_table:
+    JP jump_v0
+    JP jump_vx
+
+start:
+    LD VD, 1
+    LD VE, 1
+
+    LD V2, 1
+    LD V3, 4
+    SHR V2, V3
+    LD V0, V2
+    CALL show
+
+    ; the stored bytes are the same as the ones there, so where I ends up is what V0 loads
+    LD I, increment
+    LD V0, 0
+    LD V1, 1
+    LD V2, 2
+    LD [I], V2
+    LD V0, [I]
+    CALL show
+
+    LD V0, 0
+    LD V2, 2
+    JP V0, jump_table
+jump_v0:
+    LD V0, 0
+    JP jump_done
+jump_vx:
+    LD V0, 1
+jump_done:
+    CALL show
+
+    LD VF, 5
+    LD V2, 1
+    OR V2, V2
+    LD V0, VF
+    CALL show
+
+    LD I, bar
+    LD V2, 60
+    LD V3, 20
+    DRW V2, V3, 1
+    LD V2, 28
+    LD V3, 31
+    DRW V2, V3, 2
+
+done:
+    JP done
+
+increment:
+    DB 0, 1, 2, 3
+bar:
+    SPRITE "########" "########"
+
+INCLUDE "common.asm"
@@ -0,0 +1,29 @@
+; a box drawn in low resolution and scrolled, then the same in high resolution.
+; SCHIP scrolls by high resolution pixels in both modes, XO-CHIP by the current ones
+
+    LD I, box
+    LD V0, 12
+    LD V1, 4
+    DRW V0, V1, 8
+    SCR
+    SCD 2
+    LD V0, 40
+    DRW V0, V1, 8
+    SCL
+
+    HIGH
+    LD V0, 40
+    LD V1, 40
+    DRW V0, V1, 8
+    SCR
+    SCD 4
+    LD V0, 100
+    DRW V0, V1, 8
+    SCL
+    SCL
+
+done:
+    JP done
+
+box:
+    SPRITE "########" "#......#" "#.####.#" "#.#..#.#" "#.#..#.#" "#.####.#" "#......#" "########"